@@ -1,6 +1,26 @@
 extern crate pkg_config;
+extern crate wayland_scanner;
+
+use std::env::var;
+use std::path::Path;
+
+use wayland_scanner::{generate_code, Side};
+
+// Protocols that are not yet part of the `wayland-protocols` version we depend on,
+// their XML files are in the `protocols` directory.
+static PROTOCOLS: &[&str] =
+    &["cursor-shape-v1", "linux-dmabuf-unstable-v1", "single-pixel-buffer-v1"];
 
 fn main() {
     #[cfg(not(feature = "dlopen"))]
     pkg_config::Config::new().find("xkbcommon").unwrap();
+
+    let out_dir_str = var("OUT_DIR").unwrap();
+    let out_dir = Path::new(&out_dir_str);
+
+    for name in PROTOCOLS {
+        let protocol_file = Path::new("./protocols").join(format!("{}.xml", name));
+        println!("cargo:rerun-if-changed={}", protocol_file.display());
+        generate_code(protocol_file, out_dir.join(format!("{}_client_api.rs", name)), Side::Client);
+    }
 }
@@ -34,6 +34,13 @@ fn main() -> Result<(), ()> {
                 "  -> #{}: {} ({}), with scale factor of {}",
                 info.id, info.model, info.make, info.scale_factor
             );
+            println!(
+                "     Logical geometry is {} x {} at ({}, {})",
+                info.logical_size.0,
+                info.logical_size.1,
+                info.logical_position.0,
+                info.logical_position.1
+            );
             println!("     Possible modes are:");
             for mode in &info.modes {
                 println!(
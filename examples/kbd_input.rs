@@ -128,9 +128,8 @@ fn main() {
                     }
                 }
             }
-        } else if let Some((kbd, source)) = opt_kbd.take() {
-            // the keyboard has been removed, cleanup
-            kbd.release();
+        } else if let Some((_, source)) = opt_kbd.take() {
+            // the keyboard has been removed and SCTK released it, cleanup
             loop_handle.remove(source);
         }
     });
@@ -203,19 +202,16 @@ fn redraw(
     surface: &wl_surface::WlSurface,
     (buf_x, buf_y): (u32, u32),
 ) -> Result<(), ::std::io::Error> {
-    let (canvas, new_buffer) =
-        pool.buffer(buf_x as i32, buf_y as i32, 4 * buf_x as i32, wl_shm::Format::Argb8888)?;
-    for (i, dst_pixel) in canvas.chunks_exact_mut(4).enumerate() {
-        let x = i as u32 % buf_x;
-        let y = i as u32 / buf_x;
-        let r: u32 = min(((buf_x - x) * 0xFF) / buf_x, ((buf_y - y) * 0xFF) / buf_y);
-        let g: u32 = min((x * 0xFF) / buf_x, ((buf_y - y) * 0xFF) / buf_y);
-        let b: u32 = min(((buf_x - x) * 0xFF) / buf_x, (y * 0xFF) / buf_y);
-        let pixel: [u8; 4] = ((0xFF << 24) + (r << 16) + (g << 8) + b).to_ne_bytes();
-        dst_pixel[0] = pixel[0];
-        dst_pixel[1] = pixel[1];
-        dst_pixel[2] = pixel[2];
-        dst_pixel[3] = pixel[3];
+    let (mut canvas, new_buffer) = pool
+        .canvas_buffer(buf_x, buf_y, wl_shm::Format::Argb8888)
+        .map_err(|e| ::std::io::Error::new(::std::io::ErrorKind::Other, e))?;
+    for y in 0..buf_y {
+        for x in 0..buf_x {
+            let r: u32 = min(((buf_x - x) * 0xFF) / buf_x, ((buf_y - y) * 0xFF) / buf_y);
+            let g: u32 = min((x * 0xFF) / buf_x, ((buf_y - y) * 0xFF) / buf_y);
+            let b: u32 = min(((buf_x - x) * 0xFF) / buf_x, (y * 0xFF) / buf_y);
+            canvas.set_pixel(x, y, ((0xFF << 24) + (r << 16) + (g << 8) + b) as u64);
+        }
     }
     surface.attach(Some(&new_buffer), 0, 0);
     if surface.as_ref().version() >= 4 {
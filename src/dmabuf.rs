@@ -0,0 +1,566 @@
+//! Helpers to create buffers from DMA-BUFs
+//!
+//! Applications that allocate their buffers with GBM, Vulkan or a dma-buf heap share them with
+//! the compositor using the `zwp_linux_dmabuf_v1` global. The [`DmabufHandler`](struct.DmabufHandler.html)
+//! tracks the formats and modifiers the compositor supports, and
+//! [`DmabufParams`](struct.DmabufParams.html) creates `wl_buffer`s from the planes of a DMA-BUF.
+//!
+//! Since version 4 of the protocol, the compositor advertises its formats through
+//! [`DmabufFeedback`](struct.DmabufFeedback.html), either for the whole session or for a
+//! specific surface, sorted in tranches of decreasing preference.
+//!
+//! The `DmabufHandler` is automatically included in the
+//! [`default_environment!`](../macro.default_environment.html).
+
+use std::{
+    cell::RefCell,
+    fmt,
+    fs::File,
+    os::unix::io::{FromRawFd, RawFd},
+    rc::Rc,
+};
+
+use log::warn;
+use memmap2::MmapOptions;
+use wayland_client::{
+    protocol::{wl_buffer, wl_registry, wl_surface},
+    Attached, DispatchData, Main,
+};
+
+use crate::environment::{Environment, GlobalHandler};
+use crate::protocols::unstable::linux_dmabuf::v1::client::{
+    zwp_linux_buffer_params_v1::{self, ZwpLinuxBufferParamsV1},
+    zwp_linux_dmabuf_feedback_v1::{self, ZwpLinuxDmabufFeedbackV1},
+    zwp_linux_dmabuf_v1::{self, ZwpLinuxDmabufV1},
+};
+use crate::MissingGlobal;
+
+pub use crate::protocols::unstable::linux_dmabuf::v1::client::{
+    zwp_linux_buffer_params_v1::Flags, zwp_linux_dmabuf_feedback_v1::TrancheFlags,
+};
+
+/// The modifier indicating that the layout of the buffer is derived from the DMA-BUF itself
+pub const DRM_FORMAT_MOD_INVALID: u64 = 0x00ff_ffff_ffff_ffff;
+
+/// A format supported by the compositor, with one of its supported modifiers
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct DmabufFormat {
+    /// The `DRM_FORMAT` code, from `drm_fourcc.h`
+    pub format: u32,
+    /// The layout modifier, `DRM_FORMAT_MOD_INVALID` for an implicit modifier
+    pub modifier: u64,
+}
+
+/// A set of formats the compositor supports with the same preference
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct DmabufTranche {
+    /// The `dev_t` of the device buffers using these formats should be allocated on
+    pub device: u64,
+    /// Flags of this tranche
+    pub flags: TrancheFlags,
+    /// The supported formats and modifiers
+    pub formats: Vec<DmabufFormat>,
+}
+
+/// The dmabuf parameters the compositor prefers
+///
+/// This is sent by version 4 of the protocol, for the whole session or for a surface.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct DmabufFeedback {
+    /// The `dev_t` of the device the compositor uses when direct scan-out is not possible
+    pub main_device: u64,
+    /// The tranches of supported formats, in decreasing order of preference
+    pub tranches: Vec<DmabufTranche>,
+}
+
+impl DmabufFeedback {
+    /// All the formats of this feedback, in decreasing order of preference
+    pub fn formats(&self) -> impl Iterator<Item = &DmabufFormat> {
+        self.tranches.iter().flat_map(|tranche| tranche.formats.iter())
+    }
+}
+
+// Accumulates the events of a feedback object until `done` is received
+#[derive(Debug)]
+struct FeedbackState {
+    format_table: Vec<DmabufFormat>,
+    main_device: u64,
+    tranches: Vec<DmabufTranche>,
+    current: DmabufTranche,
+}
+
+impl FeedbackState {
+    fn new() -> FeedbackState {
+        FeedbackState {
+            format_table: Vec::new(),
+            main_device: 0,
+            tranches: Vec::new(),
+            current: DmabufTranche { device: 0, flags: TrancheFlags::empty(), formats: Vec::new() },
+        }
+    }
+
+    fn handle(&mut self, event: zwp_linux_dmabuf_feedback_v1::Event) -> Option<DmabufFeedback> {
+        use self::zwp_linux_dmabuf_feedback_v1::Event;
+        match event {
+            Event::Done => {
+                let tranches = std::mem::take(&mut self.tranches);
+                return Some(DmabufFeedback { main_device: self.main_device, tranches });
+            }
+            Event::FormatTable { fd, size } => {
+                self.format_table = read_format_table(fd, size as usize);
+            }
+            Event::MainDevice { device } => self.main_device = parse_dev(&device),
+            Event::TrancheTargetDevice { device } => self.current.device = parse_dev(&device),
+            Event::TrancheFlags { flags } => self.current.flags = flags,
+            Event::TrancheFormats { indices } => {
+                for index in indices.chunks_exact(2) {
+                    let index = u16::from_ne_bytes([index[0], index[1]]) as usize;
+                    match self.format_table.get(index) {
+                        Some(&format) => self.current.formats.push(format),
+                        None => warn!("Dmabuf feedback references an invalid format index."),
+                    }
+                }
+            }
+            Event::TrancheDone => {
+                let tranche = std::mem::replace(
+                    &mut self.current,
+                    DmabufTranche { device: 0, flags: TrancheFlags::empty(), formats: Vec::new() },
+                );
+                self.tranches.push(tranche);
+            }
+        }
+        None
+    }
+}
+
+fn read_format_table(fd: RawFd, size: usize) -> Vec<DmabufFormat> {
+    let file = unsafe { File::from_raw_fd(fd) };
+    // never map past the end of the file, touching those pages would raise SIGBUS
+    let size = match file.metadata() {
+        Ok(meta) if (meta.len() as usize) < size => {
+            warn!("Dmabuf format table is smaller than advertised: {} < {}", meta.len(), size);
+            meta.len() as usize
+        }
+        _ => size,
+    };
+    if size == 0 {
+        return Vec::new();
+    }
+    let map = match unsafe { MmapOptions::new().len(size).map_copy_read_only(&file) } {
+        Ok(map) => map,
+        Err(e) => {
+            warn!("Failed to map the dmabuf format table: {}", e);
+            return Vec::new();
+        }
+    };
+    map.chunks_exact(16)
+        .map(|entry| {
+            let mut format = [0; 4];
+            let mut modifier = [0; 8];
+            format.copy_from_slice(&entry[..4]);
+            modifier.copy_from_slice(&entry[8..]);
+            DmabufFormat {
+                format: u32::from_ne_bytes(format),
+                modifier: u64::from_ne_bytes(modifier),
+            }
+        })
+        .collect()
+}
+
+fn parse_dev(device: &[u8]) -> u64 {
+    let mut dev = [0; 8];
+    if device.len() == 8 {
+        dev.copy_from_slice(device);
+    } else {
+        warn!("Unexpected dev_t size in dmabuf feedback: {}", device.len());
+    }
+    u64::from_ne_bytes(dev)
+}
+
+#[derive(Debug)]
+struct DmabufInner {
+    formats: Vec<DmabufFormat>,
+    feedback: Option<DmabufFeedback>,
+}
+
+/// A handler for the `zwp_linux_dmabuf_v1` global
+///
+/// It binds up to version 4 of the global and collects the formats and modifiers the
+/// compositor supports, either from the `format` and `modifier` events of the older
+/// versions or from the default feedback of version 4.
+///
+/// This handler is automatically included in the
+/// [`default_environment!`](../macro.default_environment.html).
+#[derive(Debug)]
+pub struct DmabufHandler {
+    dmabuf: Option<Attached<ZwpLinuxDmabufV1>>,
+    inner: Rc<RefCell<DmabufInner>>,
+}
+
+impl DmabufHandler {
+    /// Create a new DmabufHandler
+    pub fn new() -> DmabufHandler {
+        DmabufHandler {
+            dmabuf: None,
+            inner: Rc::new(RefCell::new(DmabufInner { formats: Vec::new(), feedback: None })),
+        }
+    }
+}
+
+impl GlobalHandler<ZwpLinuxDmabufV1> for DmabufHandler {
+    fn created(
+        &mut self,
+        registry: Attached<wl_registry::WlRegistry>,
+        id: u32,
+        version: u32,
+        _: DispatchData,
+    ) {
+        let version = std::cmp::min(version, 4);
+        let dmabuf = registry.bind::<ZwpLinuxDmabufV1>(version, id);
+        let inner = self.inner.clone();
+        dmabuf.quick_assign(move |_, event, _| {
+            let mut inner = inner.borrow_mut();
+            match event {
+                // since version 3, formats are advertised along with their modifiers
+                zwp_linux_dmabuf_v1::Event::Format { format } if version < 3 => {
+                    inner.formats.push(DmabufFormat { format, modifier: DRM_FORMAT_MOD_INVALID })
+                }
+                zwp_linux_dmabuf_v1::Event::Format { .. } => {}
+                zwp_linux_dmabuf_v1::Event::Modifier { format, modifier_hi, modifier_lo } => {
+                    let modifier = (u64::from(modifier_hi) << 32) | u64::from(modifier_lo);
+                    inner.formats.push(DmabufFormat { format, modifier })
+                }
+            }
+        });
+        if version >= 4 {
+            let feedback = dmabuf.get_default_feedback();
+            let inner = self.inner.clone();
+            let mut state = FeedbackState::new();
+            feedback.quick_assign(move |_, event, _| {
+                if let Some(feedback) = state.handle(event) {
+                    inner.borrow_mut().feedback = Some(feedback);
+                }
+            });
+        }
+        self.dmabuf = Some((*dmabuf).clone());
+    }
+    fn get(&self) -> Option<Attached<ZwpLinuxDmabufV1>> {
+        self.dmabuf.clone()
+    }
+}
+
+/// An interface trait to forward the dmabuf handler capability
+///
+/// You need to implement this trait for your environment struct, by
+/// delegating it to its `DmabufHandler` field in order to get the
+/// associated methods on your [`Environment`](../environment/struct.environment.html).
+pub trait DmabufHandling {
+    /// Access the list of formats and modifiers supported by the compositor
+    fn dmabuf_formats(&self) -> Vec<DmabufFormat>;
+    /// Access the default dmabuf feedback of the compositor
+    fn dmabuf_feedback(&self) -> Option<DmabufFeedback>;
+}
+
+impl DmabufHandling for DmabufHandler {
+    fn dmabuf_formats(&self) -> Vec<DmabufFormat> {
+        let inner = self.inner.borrow();
+        match inner.feedback {
+            Some(ref feedback) if inner.formats.is_empty() => {
+                let mut formats: Vec<DmabufFormat> = Vec::new();
+                for format in feedback.formats() {
+                    if !formats.contains(format) {
+                        formats.push(*format);
+                    }
+                }
+                formats
+            }
+            _ => inner.formats.clone(),
+        }
+    }
+
+    fn dmabuf_feedback(&self) -> Option<DmabufFeedback> {
+        self.inner.borrow().feedback.clone()
+    }
+}
+
+/// A builder for `wl_buffer`s backed by DMA-BUFs
+///
+/// Add the planes of your buffer with [`add`](#method.add), then create the buffer with either
+/// [`create_immed`](#method.create_immed) or [`create`](#method.create).
+#[derive(Debug)]
+pub struct DmabufParams {
+    params: Main<ZwpLinuxBufferParamsV1>,
+}
+
+impl DmabufParams {
+    /// Start creating a new buffer
+    pub fn new(dmabuf: &Attached<ZwpLinuxDmabufV1>) -> DmabufParams {
+        let params = dmabuf.create_params();
+        // events are only of interest when using `create`
+        params.quick_assign(|_, _, _| {});
+        DmabufParams { params }
+    }
+
+    /// Add a plane to the buffer
+    ///
+    /// The file descriptor is duplicated when the request is sent, you remain responsible
+    /// for closing it.
+    pub fn add(self, fd: RawFd, plane_idx: u32, offset: u32, stride: u32, modifier: u64) -> Self {
+        self.params.add(
+            fd,
+            plane_idx,
+            offset,
+            stride,
+            (modifier >> 32) as u32,
+            (modifier & 0xffff_ffff) as u32,
+        );
+        self
+    }
+
+    /// Create the buffer immediately
+    ///
+    /// The buffer can be used right away, but if the compositor fails to import it, it may
+    /// terminate the connection. Returns `None` if the global is older than version 2 and
+    /// does not support this.
+    ///
+    /// The returned buffer has a filter ignoring its events, you can assign your own to
+    /// know when the compositor releases it.
+    pub fn create_immed(
+        self,
+        width: i32,
+        height: i32,
+        format: u32,
+        flags: Flags,
+    ) -> Option<Main<wl_buffer::WlBuffer>> {
+        if self.params.as_ref().version() < 2 {
+            self.params.destroy();
+            return None;
+        }
+        let buffer = self.params.create_immed(width, height, format, flags);
+        buffer.quick_assign(|_, _, _| {});
+        self.params.destroy();
+        Some(buffer)
+    }
+
+    /// Ask the compositor to create the buffer
+    ///
+    /// The callback is invoked with the buffer once the compositor has imported it, or with
+    /// `None` if the import failed, allowing you to fall back to another method.
+    ///
+    /// The buffer has a filter ignoring its events, you can assign your own to know when
+    /// the compositor releases it.
+    pub fn create<F>(self, width: i32, height: i32, format: u32, flags: Flags, callback: F)
+    where
+        F: FnOnce(Option<Main<wl_buffer::WlBuffer>>, DispatchData) + 'static,
+    {
+        let mut callback = Some(callback);
+        self.params.quick_assign(move |params, event, ddata| {
+            let buffer = match event {
+                zwp_linux_buffer_params_v1::Event::Created { buffer } => {
+                    buffer.quick_assign(|_, _, _| {});
+                    Some(buffer)
+                }
+                zwp_linux_buffer_params_v1::Event::Failed => None,
+            };
+            params.destroy();
+            if let Some(callback) = callback.take() {
+                callback(buffer, ddata);
+            }
+        });
+        self.params.create(width, height, format, flags);
+    }
+}
+
+/// A dmabuf feedback object for a surface
+///
+/// The compositor sends its preferred dmabuf parameters for the surface when this object is
+/// created and each time they change, for example when the surface becomes fullscreen and
+/// could be scanned-out directly. Dropping it stops the feedback.
+pub struct DmabufSurfaceFeedback {
+    feedback: Main<ZwpLinuxDmabufFeedbackV1>,
+    surface: wl_surface::WlSurface,
+}
+
+impl DmabufSurfaceFeedback {
+    /// The surface this feedback is about
+    pub fn surface(&self) -> &wl_surface::WlSurface {
+        &self.surface
+    }
+}
+
+impl Drop for DmabufSurfaceFeedback {
+    fn drop(&mut self) {
+        self.feedback.destroy();
+    }
+}
+
+impl fmt::Debug for DmabufSurfaceFeedback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DmabufSurfaceFeedback")
+            .field("feedback", &self.feedback)
+            .field("surface", &self.surface)
+            .finish()
+    }
+}
+
+impl<E: DmabufHandling> Environment<E> {
+    /// Access the list of formats and modifiers supported by the compositor
+    ///
+    /// With version 4 of the global, these are the formats of the default feedback.
+    pub fn dmabuf_formats(&self) -> Vec<DmabufFormat> {
+        self.with_inner(|inner| inner.dmabuf_formats())
+    }
+
+    /// Access the default dmabuf feedback of the compositor
+    ///
+    /// This is `None` if the compositor does not support version 4 of the global.
+    pub fn dmabuf_feedback(&self) -> Option<DmabufFeedback> {
+        self.with_inner(|inner| inner.dmabuf_feedback())
+    }
+}
+
+impl<E> Environment<E>
+where
+    E: GlobalHandler<ZwpLinuxDmabufV1>,
+{
+    /// Start creating a new buffer from DMA-BUFs
+    pub fn create_dmabuf_params(&self) -> Result<DmabufParams, MissingGlobal> {
+        let dmabuf = self.get_global::<ZwpLinuxDmabufV1>().ok_or(MissingGlobal)?;
+        Ok(DmabufParams::new(&dmabuf))
+    }
+
+    /// Receive the preferred dmabuf parameters of a surface
+    ///
+    /// The callback is invoked with the complete feedback each time it changes. This
+    /// requires version 4 of the `zwp_linux_dmabuf_v1` global, `MissingGlobal` is returned
+    /// otherwise.
+    pub fn get_dmabuf_surface_feedback<F>(
+        &self,
+        surface: &wl_surface::WlSurface,
+        mut callback: F,
+    ) -> Result<DmabufSurfaceFeedback, MissingGlobal>
+    where
+        F: FnMut(DmabufFeedback, DispatchData) + 'static,
+    {
+        let dmabuf = self.get_global::<ZwpLinuxDmabufV1>().ok_or(MissingGlobal)?;
+        if dmabuf.as_ref().version() < 4 {
+            return Err(MissingGlobal);
+        }
+        let feedback = dmabuf.get_surface_feedback(surface);
+        let mut state = FeedbackState::new();
+        feedback.quick_assign(move |_, event, ddata| {
+            if let Some(feedback) = state.handle(event) {
+                callback(feedback, ddata);
+            }
+        });
+        Ok(DmabufSurfaceFeedback { feedback, surface: surface.clone() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shm::{create_shm_file, ShmFileStrategy};
+    use std::io::Write;
+    use std::os::unix::io::IntoRawFd;
+
+    fn table_fd(entries: &[(u32, u64)], extra: &[u8]) -> (RawFd, u32) {
+        let mut file = create_shm_file(ShmFileStrategy::Auto).unwrap();
+        let mut data = Vec::new();
+        for &(format, modifier) in entries {
+            data.extend_from_slice(&format.to_ne_bytes());
+            data.extend_from_slice(&[0; 4]);
+            data.extend_from_slice(&modifier.to_ne_bytes());
+        }
+        data.extend_from_slice(extra);
+        file.write_all(&data).unwrap();
+        (file.into_raw_fd(), data.len() as u32)
+    }
+
+    fn indices(indices: &[u16]) -> Vec<u8> {
+        indices.iter().flat_map(|i| i.to_ne_bytes().to_vec()).collect()
+    }
+
+    const ARGB: u32 = 0x3432_5241;
+    const XRGB: u32 = 0x3432_5258;
+
+    #[test]
+    fn format_table() {
+        let (fd, size) = table_fd(&[(ARGB, 0), (XRGB, DRM_FORMAT_MOD_INVALID)], &[]);
+        assert_eq!(
+            read_format_table(fd, size as usize),
+            vec![
+                DmabufFormat { format: ARGB, modifier: 0 },
+                DmabufFormat { format: XRGB, modifier: DRM_FORMAT_MOD_INVALID },
+            ]
+        );
+    }
+
+    #[test]
+    fn truncated_format_table() {
+        // a trailing partial entry is ignored
+        let (fd, size) = table_fd(&[(ARGB, 0)], &[1, 2, 3]);
+        assert_eq!(
+            read_format_table(fd, size as usize),
+            vec![DmabufFormat { format: ARGB, modifier: 0 }]
+        );
+        // a file shorter than the advertised size is not read past its end
+        let (fd, size) = table_fd(&[(ARGB, 0)], &[]);
+        assert_eq!(
+            read_format_table(fd, size as usize + 4096),
+            vec![DmabufFormat { format: ARGB, modifier: 0 }]
+        );
+        let (fd, _) = table_fd(&[], &[]);
+        assert_eq!(read_format_table(fd, 16), Vec::new());
+    }
+
+    #[test]
+    fn feedback_sequencing() {
+        use self::zwp_linux_dmabuf_feedback_v1::Event;
+        let mut state = FeedbackState::new();
+        let (fd, size) = table_fd(&[(ARGB, 0), (XRGB, 0), (XRGB, DRM_FORMAT_MOD_INVALID)], &[]);
+        assert!(state.handle(Event::FormatTable { fd, size }).is_none());
+        assert!(state.handle(Event::MainDevice { device: 7u64.to_ne_bytes().to_vec() }).is_none());
+
+        assert!(state
+            .handle(Event::TrancheTargetDevice { device: 8u64.to_ne_bytes().to_vec() })
+            .is_none());
+        assert!(state.handle(Event::TrancheFlags { flags: TrancheFlags::Scanout }).is_none());
+        assert!(state.handle(Event::TrancheFormats { indices: indices(&[1]) }).is_none());
+        assert!(state.handle(Event::TrancheDone).is_none());
+
+        assert!(state
+            .handle(Event::TrancheTargetDevice { device: 7u64.to_ne_bytes().to_vec() })
+            .is_none());
+        // the out of range index is skipped
+        assert!(state.handle(Event::TrancheFormats { indices: indices(&[0, 5]) }).is_none());
+        assert!(state.handle(Event::TrancheFormats { indices: indices(&[2]) }).is_none());
+        assert!(state.handle(Event::TrancheDone).is_none());
+
+        let feedback = state.handle(Event::Done).unwrap();
+        assert_eq!(feedback.main_device, 7);
+        assert_eq!(feedback.tranches.len(), 2);
+        assert_eq!(feedback.tranches[0].device, 8);
+        assert_eq!(feedback.tranches[0].flags, TrancheFlags::Scanout);
+        assert_eq!(feedback.tranches[0].formats, vec![DmabufFormat { format: XRGB, modifier: 0 }]);
+        assert_eq!(feedback.tranches[1].device, 7);
+        assert_eq!(feedback.tranches[1].flags, TrancheFlags::empty());
+        assert_eq!(
+            feedback.formats().cloned().collect::<Vec<_>>(),
+            vec![
+                DmabufFormat { format: XRGB, modifier: 0 },
+                DmabufFormat { format: ARGB, modifier: 0 },
+                DmabufFormat { format: XRGB, modifier: DRM_FORMAT_MOD_INVALID },
+            ]
+        );
+
+        // a new round of feedback starts from an empty set of tranches, keeping the format table
+        assert!(state.handle(Event::TrancheFormats { indices: indices(&[0]) }).is_none());
+        assert!(state.handle(Event::TrancheDone).is_none());
+        let feedback = state.handle(Event::Done).unwrap();
+        assert_eq!(feedback.tranches.len(), 1);
+        assert_eq!(feedback.tranches[0].formats, vec![DmabufFormat { format: ARGB, modifier: 0 }]);
+    }
+}
@@ -10,8 +10,9 @@
 //! Wayland globals are split in two kinds, that we will call here "single" globals and "multi" globals.
 //!
 //! - "single" globals represent a capability of the server. They are generally signaled in the registry
-//!   from the start and never removed. They are signaled a single time. Examples of these globals are
-//!   `wl_compositor`, `wl_shm` or `xdg_wm_base`.
+//!   from the start and rarely removed. A single instance of them exists at a time. Examples of these
+//!   globals are `wl_compositor`, `wl_shm` or `xdg_wm_base`. Capabilities provided by helper processes,
+//!   like input methods, can be removed and signaled again when these processes restart.
 //! - "multi" globals represent a resource that the server gives you access to. These globals can be
 //!   created or removed during the run of the program, and may exist as more than one instance, each
 //!   representing a different physical resource. Examples of such globals are `wl_output` or `wl_seat`.
@@ -33,7 +34,8 @@
 //! in all SCTK modules.
 
 use std::io::Result;
-use std::rc::Rc;
+use std::ops::RangeInclusive;
+use std::rc::{self, Rc};
 use std::{cell::RefCell, fmt};
 
 use wayland_client::{
@@ -57,6 +59,19 @@ pub trait GlobalHandler<I: Interface> {
     );
     /// Access the global if it was signaled
     fn get(&self) -> Option<Attached<I>>;
+    /// The global with given id was removed
+    ///
+    /// This happens when the server withdraws the capability, for example because the
+    /// process providing it on the server side exited. If the handler bound this global, it
+    /// should drop it and be ready to bind a new instance signaled later with `created`.
+    ///
+    /// The default implementation does nothing.
+    fn removed(&mut self, _id: u32, _ddata: DispatchData) {}
+    /// The global was not given to `created`, because its version is outside the range
+    /// declared for it in the [`environment!`](../macro.environment.html) macro
+    ///
+    /// The default implementation does nothing.
+    fn refused(&mut self, _error: VersionError, _ddata: DispatchData) {}
 }
 
 /// Required trait for implementing a handler for "multi" globals
@@ -73,6 +88,11 @@ pub trait MultiGlobalHandler<I: Interface> {
     fn removed(&mut self, id: u32, ddata: DispatchData);
     /// Access all the currently existing instances
     fn get_all(&self) -> Vec<Attached<I>>;
+    /// An instance was not given to `created`, because its version is outside the range
+    /// declared for it in the [`environment!`](../macro.environment.html) macro
+    ///
+    /// The default implementation does nothing.
+    fn refused(&mut self, _error: VersionError, _ddata: DispatchData) {}
 }
 
 /*
@@ -94,6 +114,53 @@ pub struct Environment<E> {
     /// registry. See `wayland-client` documentation for details.
     pub manager: GlobalManager,
     inner: Rc<RefCell<E>>,
+    listeners: GlobalListeners,
+}
+
+type GlobalCallback = dyn FnMut(GlobalStatus, DispatchData) + 'static;
+
+type GlobalListeners = Rc<RefCell<Vec<(&'static str, rc::Weak<RefCell<GlobalCallback>>)>>>;
+
+/// A change of the globals of an interface
+///
+/// See [`Environment::listen_for_global`](struct.Environment.html#method.listen_for_global).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GlobalStatus {
+    /// A global was signaled by the registry, and given to its handler if one is declared
+    /// for its interface
+    Created {
+        /// The id of the global
+        id: u32,
+        /// The version advertised by the server
+        version: u32,
+    },
+    /// A global was signaled by the registry, but its version is outside the range declared
+    /// for it in the [`environment!`](../macro.environment.html) macro, so it was given to the
+    /// `refused` method of its handler instead
+    Refused {
+        /// The id of the global
+        id: u32,
+        /// The version advertised by the server
+        version: u32,
+    },
+    /// A global was removed, and its handler notified
+    Removed {
+        /// The id of the global
+        id: u32,
+    },
+}
+
+/// A handle to a global listener callback
+///
+/// Dropping it disables the associated callback and frees the closure.
+pub struct GlobalListener {
+    _cb: Rc<RefCell<GlobalCallback>>,
+}
+
+impl fmt::Debug for GlobalListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GlobalListener").field("_cb", &"fn() -> { ... }").finish()
+    }
 }
 
 impl<E: InnerEnv + 'static> Environment<E> {
@@ -135,6 +202,42 @@ impl<E: InnerEnv + 'static> Environment<E> {
         Ok(environment)
     }
 
+    /// Create new `Environment` without blocking
+    ///
+    /// This is similar to [`Environment::new`](#method.new), but instead of doing the
+    /// initial roundtrips it returns immediately, and invokes `ready` while the events of
+    /// the queue are dispatched, once the initial state of the environment is known: all
+    /// the globals were signaled and bound, and the events their handlers receive right
+    /// after binding (like the information of the outputs, including their logical position
+    /// and size from `zxdg_output_manager_v1`, the names of the seats or the formats of
+    /// `wl_shm`) arrived.
+    ///
+    /// This is meant for applications that start their event loop before the environment is
+    /// initialized. If you need to be notified through your event loop, you can for example
+    /// send a message through a `calloop` channel from `ready`.
+    ///
+    /// `ready` is never invoked if the connection to the server is lost during the
+    /// initialization.
+    pub fn new_async<F>(
+        display: &Attached<wl_display::WlDisplay>,
+        env: E,
+        ready: F,
+    ) -> Environment<E>
+    where
+        F: FnOnce(Environment<E>, DispatchData) + 'static,
+    {
+        let environment = Self::new_pending(display, env);
+
+        // The first sync is done once all the globals were signaled, and their handlers bound
+        // them. The second one is done once the events of the new objects arrived. The third
+        // one is done once the objects created in response to these events got their own
+        // events, like the `zxdg_output_v1` of the outputs that were done.
+        let environment_ready = environment.clone();
+        sync_chain(display.clone(), 3, move |ddata| ready(environment_ready, ddata));
+
+        environment
+    }
+
     /// Create new pending `Environment`
     ///
     /// This requires access to a `wl_display` attached to an event queue (on which the main SCTK logic
@@ -152,16 +255,45 @@ impl<E: InnerEnv + 'static> Environment<E> {
     /// roundtrips are required.
     pub fn new_pending(display: &Attached<wl_display::WlDisplay>, env: E) -> Environment<E> {
         let inner = Rc::new(RefCell::new(env));
+        let listeners: GlobalListeners = Rc::new(RefCell::new(Vec::new()));
 
         let my_inner = inner.clone();
-        let my_cb = move |event, registry, ddata: DispatchData| {
-            let mut inner = my_inner.borrow_mut();
-            inner.process_event(event, registry, ddata);
+        let my_listeners = listeners.clone();
+        let my_cb = move |event, registry, mut ddata: DispatchData| {
+            let (interface, status) = match event {
+                GlobalEvent::New { id, ref interface, version } => {
+                    (interface.clone(), GlobalStatus::Created { id, version })
+                }
+                GlobalEvent::Removed { id, ref interface } => {
+                    (interface.clone(), GlobalStatus::Removed { id })
+                }
+            };
+            let accepted = my_inner.borrow_mut().process_event(event, registry, ddata.reborrow());
+            let status = match status {
+                GlobalStatus::Created { id, version } if !accepted => {
+                    GlobalStatus::Refused { id, version }
+                }
+                status => status,
+            };
+
+            // the handler is done with the global, listeners can access it
+            let callbacks = {
+                let mut listeners = my_listeners.borrow_mut();
+                listeners.retain(|(_, cb)| cb.upgrade().is_some());
+                listeners
+                    .iter()
+                    .filter(|(name, _)| *name == interface)
+                    .filter_map(|(_, cb)| cb.upgrade())
+                    .collect::<Vec<_>>()
+            };
+            for cb in callbacks {
+                (*cb.borrow_mut())(status, ddata.reborrow());
+            }
         };
 
         let manager = GlobalManager::new_with_cb(display, my_cb);
 
-        Self { manager, inner }
+        Self { manager, inner, listeners }
     }
 }
 
@@ -194,6 +326,17 @@ impl<E> Environment<E> {
         }
     }
 
+    /// The version a "single" global was bound with
+    ///
+    /// It returns `None` if the global has not (yet) been bound.
+    pub fn get_global_version<I>(&self) -> Option<u32>
+    where
+        I: Interface + AsRef<Proxy<I>> + From<Proxy<I>>,
+        E: GlobalHandler<I>,
+    {
+        self.inner.borrow().get().map(|global| (*global).as_ref().version())
+    }
+
     /// Access all instances of a "multi" global
     ///
     /// This will return a `Vec` containing all currently existing instances of the
@@ -207,6 +350,35 @@ impl<E> Environment<E> {
         self.inner.borrow().get_all()
     }
 
+    /// Insert a new listener for the globals of an interface
+    ///
+    /// The provided closure will be invoked each time a global of interface `I` is signaled
+    /// or removed by the registry, after the handler declared for it in the
+    /// [`environment!`](../macro.environment.html) macro processed it. This lets you enable
+    /// features that depend on a global when it appears after the initialization, for example
+    /// with [`get_global`](#method.get_global) for a "single" global. Globals of interfaces
+    /// that are not routed to a handler are signaled as well, but they are not bound. Globals
+    /// refused because of their version are signaled as `GlobalStatus::Refused`.
+    ///
+    /// Note that if globals already exist when this callback is setup, it'll not be invoked on
+    /// them.
+    ///
+    /// The interface is given as the first type parameter, as in
+    /// `env.listen_for_global::<ZwpIdleInhibitManagerV1, _>(|status, ddata| { ... })`.
+    ///
+    /// The returned [`GlobalListener`](struct.GlobalListener.html) keeps your callback alive,
+    /// dropping it will disable it.
+    #[must_use = "the returned GlobalListener keeps your callback alive, dropping it will disable it"]
+    pub fn listen_for_global<I, F>(&self, f: F) -> GlobalListener
+    where
+        I: Interface,
+        F: FnMut(GlobalStatus, DispatchData) + 'static,
+    {
+        let rc = Rc::new(RefCell::new(f)) as Rc<RefCell<GlobalCallback>>;
+        self.listeners.borrow_mut().push((I::NAME, Rc::downgrade(&rc)));
+        GlobalListener { _cb: rc }
+    }
+
     /// Access the inner environment
     ///
     /// This gives your access, via a closure, to the inner type you declared
@@ -222,7 +394,11 @@ impl<E> Environment<E> {
 
 impl<E> Clone for Environment<E> {
     fn clone(&self) -> Environment<E> {
-        Environment { manager: self.manager.clone(), inner: self.inner.clone() }
+        Environment {
+            manager: self.manager.clone(),
+            inner: self.inner.clone(),
+            listeners: self.listeners.clone(),
+        }
     }
 }
 
@@ -238,18 +414,39 @@ where
     }
 }
 
+// Invoke `cb` once `count` successive `wl_display.sync` requests are done
+fn sync_chain<F>(display: Attached<wl_display::WlDisplay>, count: u32, cb: F)
+where
+    F: FnOnce(DispatchData) + 'static,
+{
+    let mut cb = Some(cb);
+    let sync_display = display.clone();
+    display.sync().quick_assign(move |_, _, ddata| {
+        if let Some(cb) = cb.take() {
+            if count > 1 {
+                sync_chain(sync_display.clone(), count - 1, cb);
+            } else {
+                cb(ddata);
+            }
+        }
+    });
+}
+
 /// Internal trait for the `Environment` logic
 ///
 /// This trait is automatically implemented by the [`environment!`](../macro.environment.html)
 /// macro, you should not implement it manually unless you seriously want to.
 pub trait InnerEnv {
     /// Process a `GlobalEvent`
+    ///
+    /// Returns `false` if a new global was refused because its version is outside the range
+    /// declared for it, and `true` otherwise.
     fn process_event(
         &mut self,
         event: GlobalEvent,
         registry: Attached<wl_registry::WlRegistry>,
         data: DispatchData,
-    );
+    ) -> bool;
 }
 
 /*
@@ -263,15 +460,51 @@ pub trait InnerEnv {
 ///
 /// It is appropriate for globals that never generate events, like `wl_compositor`
 /// or `wl_data_device_manager`.
+///
+/// The global is bound with the highest version supported both by the server and by this
+/// handler, which by default supports all the versions known to the protocol bindings. If the
+/// server only supports versions below the minimum version of the handler, or below the range
+/// given to the [`environment!`](../macro.environment.html) macro, the global is not bound and
+/// the error is available with [`version_error`](#method.version_error).
+///
+/// If the server removes the global, it is dropped, and a new instance is bound if the server
+/// signals one later.
 #[derive(Debug)]
 pub struct SimpleGlobal<I: Interface> {
     global: Option<Attached<I>>,
+    id: Option<u32>,
+    versions: RangeInclusive<u32>,
+    error: Option<VersionError>,
 }
 
 impl<I: Interface> SimpleGlobal<I> {
     /// Create a new handler
     pub fn new() -> SimpleGlobal<I> {
-        SimpleGlobal { global: None }
+        SimpleGlobal::with_versions(1..=I::VERSION)
+    }
+
+    /// Create a new handler supporting given range of versions
+    ///
+    /// The upper bound is capped to the version of the protocol bindings.
+    pub fn with_versions(versions: RangeInclusive<u32>) -> SimpleGlobal<I> {
+        SimpleGlobal { global: None, id: None, versions, error: None }
+    }
+
+    /// The range of versions supported by this handler
+    pub fn versions(&self) -> RangeInclusive<u32> {
+        self.versions.clone()
+    }
+
+    /// The error that prevented the global from being bound, if any
+    pub fn version_error(&self) -> Option<&VersionError> {
+        self.error.as_ref()
+    }
+}
+
+impl<I: Interface + Clone + From<Proxy<I>> + AsRef<Proxy<I>>> SimpleGlobal<I> {
+    /// The version the global was bound with, if it was
+    pub fn version(&self) -> Option<u32> {
+        self.global.as_ref().map(|global| (**global).as_ref().version())
     }
 }
 
@@ -283,11 +516,80 @@ impl<I: Interface + Clone + From<Proxy<I>> + AsRef<Proxy<I>>> GlobalHandler<I> f
         version: u32,
         _: DispatchData,
     ) {
-        self.global = Some((*registry.bind::<I>(version, id)).clone())
+        match negotiate_version::<I>(version, self.versions.clone()) {
+            Ok(version) => {
+                self.error = None;
+                self.id = Some(id);
+                self.global = Some((*registry.bind::<I>(version, id)).clone())
+            }
+            Err(error) => self.error = Some(error),
+        }
     }
     fn get(&self) -> Option<Attached<I>> {
         self.global.clone()
     }
+    fn removed(&mut self, id: u32, _: DispatchData) {
+        if self.id == Some(id) {
+            self.id = None;
+            self.global = None;
+        }
+    }
+    fn refused(&mut self, error: VersionError, _: DispatchData) {
+        self.error = Some(error);
+    }
+}
+
+/*
+ * Version negotiation
+ */
+
+/// An error preventing a global from being bound
+///
+/// The server only supports versions of the global that are older than the minimum version
+/// required by the client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionError {
+    /// The interface of the global
+    pub interface: &'static str,
+    /// The version advertised by the server
+    pub advertised: u32,
+    /// The minimum version required by the client
+    pub required: u32,
+}
+
+impl std::error::Error for VersionError {}
+
+impl fmt::Display for VersionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the server supports version {} of {}, but at least version {} is required",
+            self.advertised, self.interface, self.required
+        )
+    }
+}
+
+/// Choose the version to bind a global with
+///
+/// Given the version advertised by the server and the range of versions supported by the
+/// client, this returns the highest version supported by both. The upper bound of the range is
+/// capped to the version of the protocol bindings, as binding a newer version would lead to
+/// protocol errors.
+///
+/// If the server version is below the minimum supported version, the error is logged and
+/// returned.
+pub fn negotiate_version<I: Interface>(
+    advertised: u32,
+    versions: RangeInclusive<u32>,
+) -> std::result::Result<u32, VersionError> {
+    let required = std::cmp::max(*versions.start(), 1);
+    let max = std::cmp::min(*versions.end(), I::VERSION);
+    if advertised < required || max < required {
+        let error = VersionError { interface: I::NAME, advertised, required };
+        log::error!("[SCTK] Not binding {}: {}", I::NAME, error);
+        return Err(error);
+    }
+    Ok(std::cmp::min(advertised, max))
 }
 
 /*
@@ -333,11 +635,16 @@ impl<I: Interface + Clone + From<Proxy<I>> + AsRef<Proxy<I>>> GlobalHandler<I> f
 ///   appropriate `GlobalHandler` or `MultiGlobalHandler` trait
 ///
 /// It is possible to route several globals to the same field as long as it implements all the appropriate traits.
+///
+/// A range of supported versions can be given for a global, as `$type => $name @ $range`, for example
+/// `WlCompositor => compositor @ 3..=4`. The handler is then given the highest version supported both
+/// by the server and by the range (see [`negotiate_version`](environment/fn.negotiate_version.html)),
+/// and its `refused` method is called instead of `created` if the server version is below the range.
 #[macro_export]
 macro_rules! environment {
     ($env_name:ident,
-        singles = [$($sty:ty => $sname:ident),* $(,)?],
-        multis = [$($mty:ty => $mname:ident),* $(,)?]$(,)?
+        singles = [$($sty:ty => $sname:ident $(@ $srange:expr)?),* $(,)?],
+        multis = [$($mty:ty => $mname:ident $(@ $mrange:expr)?),* $(,)?]$(,)?
     ) => {
         impl $crate::environment::InnerEnv for $env_name {
             fn process_event(
@@ -345,24 +652,50 @@ macro_rules! environment {
                 event: $crate::reexports::client::GlobalEvent,
                 registry: $crate::reexports::client::Attached<$crate::reexports::client::protocol::wl_registry::WlRegistry>,
                 ddata: $crate::reexports::client::DispatchData,
-            ) {
+            ) -> bool {
                 match event {
                     $crate::reexports::client::GlobalEvent::New { id, interface, version } => match &interface[..] {
                         $(
-                            <$sty as $crate::reexports::client::Interface>::NAME => $crate::environment::GlobalHandler::<$sty>::created(&mut self.$sname, registry, id, version, ddata),
+                            <$sty as $crate::reexports::client::Interface>::NAME => {
+                                $(
+                                    let version = match $crate::environment::negotiate_version::<$sty>(version, $srange) {
+                                        Ok(version) => version,
+                                        Err(error) => {
+                                            $crate::environment::GlobalHandler::<$sty>::refused(&mut self.$sname, error, ddata);
+                                            return false;
+                                        }
+                                    };
+                                )?
+                                $crate::environment::GlobalHandler::<$sty>::created(&mut self.$sname, registry, id, version, ddata)
+                            },
                         )*
                         $(
-                            <$mty as $crate::reexports::client::Interface>::NAME => $crate::environment::MultiGlobalHandler::<$mty>::created(&mut self.$mname, registry, id, version, ddata),
+                            <$mty as $crate::reexports::client::Interface>::NAME => {
+                                $(
+                                    let version = match $crate::environment::negotiate_version::<$mty>(version, $mrange) {
+                                        Ok(version) => version,
+                                        Err(error) => {
+                                            $crate::environment::MultiGlobalHandler::<$mty>::refused(&mut self.$mname, error, ddata);
+                                            return false;
+                                        }
+                                    };
+                                )?
+                                $crate::environment::MultiGlobalHandler::<$mty>::created(&mut self.$mname, registry, id, version, ddata)
+                            },
                         )*
                         _ => { /* ignore unkown globals */ }
                     },
                     $crate::reexports::client::GlobalEvent::Removed { id, interface } => match &interface[..] {
+                        $(
+                            <$sty as $crate::reexports::client::Interface>::NAME => $crate::environment::GlobalHandler::<$sty>::removed(&mut self.$sname, id, ddata),
+                        )*
                         $(
                             <$mty as $crate::reexports::client::Interface>::NAME => $crate::environment::MultiGlobalHandler::<$mty>::removed(&mut self.$mname, id, ddata),
                         )*
                         _ => { /* ignore unknown globals */ }
                     }
                 }
+                true
             }
         }
 
@@ -374,6 +707,12 @@ macro_rules! environment {
                 fn get(&self) -> Option<$crate::reexports::client::Attached<$sty>> {
                     $crate::environment::GlobalHandler::<$sty>::get(&self.$sname)
                 }
+                fn removed(&mut self, id: u32, ddata: $crate::reexports::client::DispatchData) {
+                    $crate::environment::GlobalHandler::<$sty>::removed(&mut self.$sname, id, ddata)
+                }
+                fn refused(&mut self, error: $crate::environment::VersionError, ddata: $crate::reexports::client::DispatchData) {
+                    $crate::environment::GlobalHandler::<$sty>::refused(&mut self.$sname, error, ddata)
+                }
             }
         )*
 
@@ -388,7 +727,70 @@ macro_rules! environment {
                 fn get_all(&self) -> Vec<$crate::reexports::client::Attached<$mty>> {
                     $crate::environment::MultiGlobalHandler::<$mty>::get_all(&self.$mname)
                 }
+                fn refused(&mut self, error: $crate::environment::VersionError, ddata: $crate::reexports::client::DispatchData) {
+                    $crate::environment::MultiGlobalHandler::<$mty>::refused(&mut self.$mname, error, ddata)
+                }
             }
         )*
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wayland_client::protocol::wl_compositor::WlCompositor;
+
+    // the version of `wl_compositor` in the protocol bindings
+    const MAX: u32 = <WlCompositor as Interface>::VERSION;
+
+    #[test]
+    fn negotiate_highest_common_version() {
+        assert_eq!(negotiate_version::<WlCompositor>(3, 1..=MAX), Ok(3));
+        assert_eq!(negotiate_version::<WlCompositor>(MAX, 1..=2), Ok(2));
+        assert_eq!(negotiate_version::<WlCompositor>(2, 2..=2), Ok(2));
+    }
+
+    #[test]
+    fn negotiate_server_above_bindings() {
+        assert_eq!(negotiate_version::<WlCompositor>(MAX + 3, 1..=MAX + 10), Ok(MAX));
+        assert_eq!(negotiate_version::<WlCompositor>(MAX + 3, 2..=100), Ok(MAX));
+    }
+
+    #[test]
+    fn negotiate_server_below_minimum() {
+        assert_eq!(
+            negotiate_version::<WlCompositor>(2, 3..=MAX),
+            Err(VersionError { interface: "wl_compositor", advertised: 2, required: 3 })
+        );
+        // the minimum is above what the bindings support
+        assert_eq!(
+            negotiate_version::<WlCompositor>(MAX + 2, MAX + 1..=MAX + 2),
+            Err(VersionError {
+                interface: "wl_compositor",
+                advertised: MAX + 2,
+                required: MAX + 1
+            })
+        );
+    }
+
+    #[test]
+    fn negotiate_zero_start() {
+        // version 0 does not exist, the range starts at 1
+        assert_eq!(negotiate_version::<WlCompositor>(3, 0..=MAX), Ok(3));
+        assert_eq!(
+            negotiate_version::<WlCompositor>(0, 0..=MAX),
+            Err(VersionError { interface: "wl_compositor", advertised: 0, required: 1 })
+        );
+    }
+
+    #[test]
+    fn negotiate_empty_range() {
+        #[allow(clippy::reversed_empty_ranges)]
+        let range = 3..=2;
+        assert_eq!(
+            negotiate_version::<WlCompositor>(MAX, range),
+            Err(VersionError { interface: "wl_compositor", advertised: MAX, required: 3 })
+        );
+        assert!(negotiate_version::<WlCompositor>(MAX, 0..=0).is_err());
+    }
+}
@@ -0,0 +1,425 @@
+//! Control of the gamma ramps of the outputs
+//!
+//! Compositors based on wlroots let a client replace the gamma ramps of the outputs through
+//! the `zwlr_gamma_control_manager_v1` global, which is what night-light tools use to shift
+//! the colors of the screen towards red in the evening.
+//!
+//! A [`GammaControl`](struct.GammaControl.html) is created for a `wl_output`, using
+//! [`Environment::create_gamma_control`](../environment/struct.Environment.html#method.create_gamma_control),
+//! or for all the outputs with
+//! [`Environment::create_gamma_controls`](../environment/struct.Environment.html#method.create_gamma_controls).
+//! Once the compositor has sent the size of the ramps of the output, a
+//! [`GammaRamp`](struct.GammaRamp.html) of this size can be set. The ramps are restored by the
+//! compositor when the control is dropped.
+//!
+//! Only one client can control the gamma of an output at a time, the control of other clients
+//! fails.
+//!
+//! The `zwlr_gamma_control_manager_v1` global is not included in the
+//! [`default_environment!`](../macro.default_environment.html), you can add it as a
+//! [`SimpleGlobal`](../environment/struct.SimpleGlobal.html).
+
+use std::{
+    cell::{Cell, RefCell},
+    fmt,
+    io::{self, Seek, SeekFrom, Write},
+    os::unix::io::AsRawFd,
+    rc::Rc,
+};
+
+use wayland_client::{protocol::wl_output, Attached, DispatchData};
+use wayland_protocols::wlr::unstable::gamma_control::v1::client::zwlr_gamma_control_v1;
+
+use crate::environment::{Environment, GlobalHandler, MultiGlobalHandler};
+use crate::shm::{create_shm_file, shm_file_strategy};
+use crate::MissingGlobal;
+
+pub use wayland_protocols::wlr::unstable::gamma_control::v1::client::{
+    zwlr_gamma_control_manager_v1::ZwlrGammaControlManagerV1,
+    zwlr_gamma_control_v1::ZwlrGammaControlV1,
+};
+
+/// An event of a [`GammaControl`](struct.GammaControl.html)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GammaEvent {
+    /// The size of the gamma ramps of the output is known, ramps can be set
+    ///
+    /// A size of 0 means the gamma of the output cannot be changed.
+    Size(u32),
+    /// The control failed and is no longer valid
+    ///
+    /// This happens when another client already controls the gamma of the output, when the
+    /// output is removed, or when an invalid ramp was set.
+    Failed,
+}
+
+/// An error of [`GammaControl::set_gamma`](struct.GammaControl.html#method.set_gamma)
+#[derive(Debug)]
+pub enum GammaError {
+    /// The control failed, see [`GammaEvent::Failed`](enum.GammaEvent.html#variant.Failed)
+    Failed,
+    /// The compositor has not sent the size of the ramps yet
+    UnknownSize,
+    /// The ramp does not have the size expected by the compositor
+    SizeMismatch {
+        /// The size of the ramps of the output
+        expected: u32,
+        /// The size of the given ramp
+        found: usize,
+    },
+    /// Writing the ramp to a shared memory file failed
+    Io(io::Error),
+}
+
+impl std::error::Error for GammaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            GammaError::Io(ref error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for GammaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            GammaError::Failed => f.write_str("the gamma control failed"),
+            GammaError::UnknownSize => f.write_str("the size of the gamma ramps is not known yet"),
+            GammaError::SizeMismatch { expected, found } => {
+                write!(f, "expected gamma ramps of size {}, found {}", expected, found)
+            }
+            GammaError::Io(ref e) => write!(f, "failed to write the gamma ramps: {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for GammaError {
+    fn from(e: io::Error) -> GammaError {
+        GammaError::Io(e)
+    }
+}
+
+/// The gamma ramps of an output
+///
+/// Each channel maps the input intensity, from 0 at the start of the ramp to the maximum at
+/// its end, to an output intensity between 0 and `u16::MAX`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GammaRamp {
+    /// The ramp of the red channel
+    pub red: Vec<u16>,
+    /// The ramp of the green channel
+    pub green: Vec<u16>,
+    /// The ramp of the blue channel
+    pub blue: Vec<u16>,
+}
+
+impl GammaRamp {
+    /// The ramps leaving the colors unchanged
+    pub fn identity(size: u32) -> GammaRamp {
+        GammaRamp::from_factors(size, (1.0, 1.0, 1.0))
+    }
+
+    /// The ramps scaling each channel by a factor
+    ///
+    /// The factors are clamped to the `0.0..=1.0` range.
+    pub fn from_factors(size: u32, (red, green, blue): (f64, f64, f64)) -> GammaRamp {
+        GammaRamp {
+            red: linear_ramp(size, red),
+            green: linear_ramp(size, green),
+            blue: linear_ramp(size, blue),
+        }
+    }
+
+    /// The ramps giving white the color of a black body at given temperature
+    ///
+    /// `temperature` is in Kelvin, 6600 K leaves white unchanged and lower temperatures are
+    /// warmer. `brightness` scales all the channels, 1.0 keeps the full brightness.
+    pub fn from_temperature(size: u32, temperature: u32, brightness: f64) -> GammaRamp {
+        let (red, green, blue) = temperature_to_rgb(temperature);
+        GammaRamp::from_factors(size, (red * brightness, green * brightness, blue * brightness))
+    }
+
+    /// The number of entries of each channel
+    ///
+    /// Returns `None` if the channels do not all have the same size.
+    pub fn size(&self) -> Option<usize> {
+        let size = self.red.len();
+        if self.green.len() == size && self.blue.len() == size {
+            Some(size)
+        } else {
+            None
+        }
+    }
+
+    fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(self.red.len() * 6);
+        for value in self.red.iter().chain(&self.green).chain(&self.blue) {
+            bytes.extend_from_slice(&value.to_ne_bytes());
+        }
+        writer.write_all(&bytes)
+    }
+}
+
+// clamp to the 0.0..=1.0 range, mapping NaN to 0
+fn unit(value: f64) -> f64 {
+    if value > 1.0 {
+        1.0
+    } else if value >= 0.0 {
+        value
+    } else {
+        0.0
+    }
+}
+
+fn linear_ramp(size: u32, factor: f64) -> Vec<u16> {
+    let factor = unit(factor);
+    let last = std::cmp::max(size, 2) - 1;
+    (0..size).map(|i| (f64::from(i) / f64::from(last) * factor * RAMP_MAX).round() as u16).collect()
+}
+
+// Approximation of the color of a black body by Tanner Helland, accurate enough for
+// temperatures between 1000 K and 40000 K
+fn temperature_to_rgb(temperature: u32) -> (f64, f64, f64) {
+    let temperature = match temperature {
+        0..=999 => 1000,
+        t if t > 40000 => 40000,
+        t => t,
+    };
+    let t = f64::from(temperature) / 100.0;
+    let red = if t <= 66.0 { 255.0 } else { 329.698_727_446 * (t - 60.0).powf(-0.133_204_759_2) };
+    let green = if t <= 66.0 {
+        99.470_802_586_1 * t.ln() - 161.119_568_166_1
+    } else {
+        288.122_169_528_3 * (t - 60.0).powf(-0.075_514_849_2)
+    };
+    let blue = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.517_731_223_1 * (t - 10.0).ln() - 305.044_792_730_7
+    };
+    (unit(red / 255.0), unit(green / 255.0), unit(blue / 255.0))
+}
+
+// the maximum value of a ramp entry
+const RAMP_MAX: f64 = 65535.0;
+
+type GammaCallback = dyn FnMut(wl_output::WlOutput, GammaEvent, DispatchData) + 'static;
+
+/// A control of the gamma ramps of an output
+///
+/// Dropping it restores the original gamma ramps of the output.
+pub struct GammaControl {
+    control: ZwlrGammaControlV1,
+    output: wl_output::WlOutput,
+    size: Rc<Cell<Option<u32>>>,
+    failed: Rc<Cell<bool>>,
+}
+
+impl GammaControl {
+    /// Create a control of the gamma ramps of an output
+    ///
+    /// The callback is invoked when the compositor sends the size of the ramps of the output,
+    /// and if the control fails.
+    pub fn new<F>(
+        manager: &Attached<ZwlrGammaControlManagerV1>,
+        output: &wl_output::WlOutput,
+        callback: F,
+    ) -> GammaControl
+    where
+        F: FnMut(wl_output::WlOutput, GammaEvent, DispatchData) + 'static,
+    {
+        GammaControl::with_shared_callback(manager, output, Rc::new(RefCell::new(callback)))
+    }
+
+    fn with_shared_callback(
+        manager: &Attached<ZwlrGammaControlManagerV1>,
+        output: &wl_output::WlOutput,
+        callback: Rc<RefCell<GammaCallback>>,
+    ) -> GammaControl {
+        let control = manager.get_gamma_control(output);
+        let size = Rc::new(Cell::new(None));
+        let failed = Rc::new(Cell::new(false));
+        let (event_size, event_failed, event_output) =
+            (size.clone(), failed.clone(), output.clone());
+        control.quick_assign(move |_, event, ddata| {
+            let event = match event {
+                zwlr_gamma_control_v1::Event::GammaSize { size } => {
+                    event_size.set(Some(size));
+                    GammaEvent::Size(size)
+                }
+                zwlr_gamma_control_v1::Event::Failed => {
+                    event_failed.set(true);
+                    GammaEvent::Failed
+                }
+                _ => return,
+            };
+            (*callback.borrow_mut())(event_output.clone(), event, ddata);
+        });
+        GammaControl { control: control.detach(), output: output.clone(), size, failed }
+    }
+
+    /// The output controlled by this control
+    pub fn output(&self) -> &wl_output::WlOutput {
+        &self.output
+    }
+
+    /// The size of the gamma ramps of the output, if it was sent by the compositor
+    pub fn ramp_size(&self) -> Option<u32> {
+        self.size.get()
+    }
+
+    /// Whether this control failed
+    ///
+    /// A failed control can no longer be used, and should be dropped.
+    pub fn is_failed(&self) -> bool {
+        self.failed.get()
+    }
+
+    /// Set the gamma ramps of the output
+    ///
+    /// The ramps must have the size given by [`ramp_size`](#method.ramp_size). They are
+    /// written to a shared memory file, created with the strategy of the
+    /// [`shm`](../shm/fn.set_shm_file_strategy.html) module, which is sent to the compositor.
+    pub fn set_gamma(&self, ramp: &GammaRamp) -> Result<(), GammaError> {
+        if self.failed.get() {
+            return Err(GammaError::Failed);
+        }
+        let expected = self.size.get().ok_or(GammaError::UnknownSize)?;
+        match ramp.size() {
+            Some(size) if size == expected as usize => {}
+            found => {
+                let found = found.unwrap_or(ramp.red.len());
+                return Err(GammaError::SizeMismatch { expected, found });
+            }
+        }
+        let mut file = create_shm_file(shm_file_strategy()).map_err(io::Error::from)?;
+        ramp.write_to(&mut file)?;
+        file.seek(SeekFrom::Start(0))?;
+        // the file descriptor is duplicated when the request is sent, so the file can be
+        // closed right away
+        self.control.set_gamma(file.as_raw_fd());
+        Ok(())
+    }
+}
+
+impl Drop for GammaControl {
+    fn drop(&mut self) {
+        self.control.destroy();
+    }
+}
+
+impl fmt::Debug for GammaControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GammaControl")
+            .field("control", &self.control)
+            .field("output", &self.output)
+            .field("size", &self.size)
+            .field("failed", &self.failed)
+            .finish()
+    }
+}
+
+impl<E> Environment<E>
+where
+    E: GlobalHandler<ZwlrGammaControlManagerV1>,
+{
+    /// Create a control of the gamma ramps of an output
+    ///
+    /// See [`GammaControl::new`](../gamma_control/struct.GammaControl.html#method.new) for
+    /// details.
+    pub fn create_gamma_control<F>(
+        &self,
+        output: &wl_output::WlOutput,
+        callback: F,
+    ) -> Result<GammaControl, MissingGlobal>
+    where
+        F: FnMut(wl_output::WlOutput, GammaEvent, DispatchData) + 'static,
+    {
+        let manager = self.get_global::<ZwlrGammaControlManagerV1>().ok_or(MissingGlobal)?;
+        Ok(GammaControl::new(&manager, output, callback))
+    }
+}
+
+impl<E> Environment<E>
+where
+    E: GlobalHandler<ZwlrGammaControlManagerV1> + MultiGlobalHandler<wl_output::WlOutput>,
+{
+    /// Create a control of the gamma ramps of each output
+    ///
+    /// The callback is shared by all the controls, and receives the output each event is
+    /// about. Outputs that appear later are not included, use
+    /// [`listen_for_outputs`](#method.listen_for_outputs) to create their control.
+    pub fn create_gamma_controls<F>(&self, callback: F) -> Result<Vec<GammaControl>, MissingGlobal>
+    where
+        F: FnMut(wl_output::WlOutput, GammaEvent, DispatchData) + 'static,
+    {
+        let manager = self.get_global::<ZwlrGammaControlManagerV1>().ok_or(MissingGlobal)?;
+        let callback = Rc::new(RefCell::new(callback)) as Rc<RefCell<GammaCallback>>;
+        Ok(self
+            .get_all_globals::<wl_output::WlOutput>()
+            .into_iter()
+            .map(|output| GammaControl::with_shared_callback(&manager, &output, callback.clone()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identity_endpoints() {
+        let ramp = GammaRamp::identity(256);
+        assert_eq!(ramp.size(), Some(256));
+        for channel in &[&ramp.red, &ramp.green, &ramp.blue] {
+            assert_eq!(channel[0], 0);
+            assert_eq!(channel[255], 65535);
+            assert!(channel.windows(2).all(|w| w[0] <= w[1]));
+        }
+        assert_eq!(ramp.red[128], (128.0 / 255.0 * RAMP_MAX).round() as u16);
+    }
+
+    #[test]
+    fn degenerate_sizes() {
+        assert_eq!(GammaRamp::identity(0).size(), Some(0));
+        let ramp = GammaRamp::identity(1);
+        assert_eq!(ramp.size(), Some(1));
+        assert_eq!(ramp.red, vec![0]);
+        assert_eq!(GammaRamp::identity(2).red, vec![0, 65535]);
+    }
+
+    #[test]
+    fn factors_are_clamped() {
+        let ramp = GammaRamp::from_factors(3, (std::f64::NAN, -1.0, 2.0));
+        assert_eq!(ramp.red, vec![0, 0, 0]);
+        assert_eq!(ramp.green, vec![0, 0, 0]);
+        assert_eq!(ramp.blue, vec![0, 32768, 65535]);
+        let ramp = GammaRamp::from_factors(3, (0.5, std::f64::INFINITY, std::f64::NEG_INFINITY));
+        assert_eq!(ramp.red, vec![0, 16384, 32768]);
+        assert_eq!(ramp.green, vec![0, 32768, 65535]);
+        assert_eq!(ramp.blue, vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn temperature() {
+        let (red, green, blue) = temperature_to_rgb(6600);
+        assert!(
+            (red - 1.0).abs() < 0.01 && (green - 1.0).abs() < 0.01 && (blue - 1.0).abs() < 0.01
+        );
+        assert_eq!(GammaRamp::from_temperature(16, 6600, 1.0), GammaRamp::identity(16));
+        // warm temperatures lower blue first
+        let (red, green, blue) = temperature_to_rgb(3000);
+        assert!(red > green && green > blue && blue > 0.0);
+        assert_eq!(temperature_to_rgb(0), temperature_to_rgb(1000));
+        assert_eq!(temperature_to_rgb(100_000), temperature_to_rgb(40000));
+        for &t in &[0, 1000, 1900, 6500, 6600, 6700, 40000, 100_000] {
+            let (red, green, blue) = temperature_to_rgb(t);
+            assert!([red, green, blue].iter().all(|c| *c >= 0.0 && *c <= 1.0), "{}", t);
+        }
+        // brightness scales all the channels
+        let ramp = GammaRamp::from_temperature(3, 6600, 0.5);
+        assert_eq!(ramp, GammaRamp::from_factors(3, (0.5, 0.5, 0.5)));
+    }
+}
@@ -0,0 +1,76 @@
+//! Helpers to prevent the system from going idle
+//!
+//! Applications like video players or presentation tools need to keep the screen awake
+//! while they are in use. This is done by creating an [`IdleInhibitor`](struct.IdleInhibitor.html)
+//! for one of their surfaces, using
+//! [`Environment::create_idle_inhibitor`](../environment/struct.Environment.html#method.create_idle_inhibitor).
+//!
+//! The `zwp_idle_inhibit_manager_v1` global needs to be handled by your environment, it is
+//! automatically included in the [`default_environment!`](../macro.default_environment.html)
+//! as a [`SimpleGlobal`](../environment/struct.SimpleGlobal.html).
+
+use wayland_client::protocol::wl_surface;
+use wayland_protocols::unstable::idle_inhibit::v1::client::{
+    zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1, zwp_idle_inhibitor_v1::ZwpIdleInhibitorV1,
+};
+
+use crate::environment::{Environment, GlobalHandler};
+use crate::MissingGlobal;
+
+/// A guard inhibiting the idle behavior of the system
+///
+/// As long as this object exists and its surface is visible, the compositor will not
+/// dim or blank the screen, or start the screensaver. Dropping it removes the inhibition.
+///
+/// The inhibition ends with its surface: the compositor ignores inhibitors whose surface was
+/// destroyed, and dropping the guard afterwards is harmless. To tie the inhibition to a
+/// [`Window`](../window/struct.Window.html), let the window own it with
+/// [`Window::inhibit_idle`](../window/struct.Window.html#method.inhibit_idle).
+#[derive(Debug)]
+pub struct IdleInhibitor {
+    inhibitor: ZwpIdleInhibitorV1,
+    surface: wl_surface::WlSurface,
+}
+
+impl IdleInhibitor {
+    /// Create an inhibitor for given surface
+    pub fn new(
+        manager: &ZwpIdleInhibitManagerV1,
+        surface: &wl_surface::WlSurface,
+    ) -> IdleInhibitor {
+        let inhibitor = manager.create_inhibitor(surface);
+        IdleInhibitor { inhibitor: inhibitor.detach(), surface: surface.clone() }
+    }
+
+    /// The surface this inhibitor was created for
+    pub fn surface(&self) -> &wl_surface::WlSurface {
+        &self.surface
+    }
+}
+
+impl Drop for IdleInhibitor {
+    fn drop(&mut self) {
+        // the inhibitor outlives its surface, but not the connection
+        if self.inhibitor.as_ref().is_alive() {
+            self.inhibitor.destroy();
+        }
+    }
+}
+
+impl<E> Environment<E>
+where
+    E: GlobalHandler<ZwpIdleInhibitManagerV1>,
+{
+    /// Inhibit the idle behavior of the system while given surface is visible
+    ///
+    /// The inhibition lasts as long as the returned `IdleInhibitor` is alive.
+    ///
+    /// Returns an error if the compositor does not support idle inhibition.
+    pub fn create_idle_inhibitor(
+        &self,
+        surface: &wl_surface::WlSurface,
+    ) -> Result<IdleInhibitor, MissingGlobal> {
+        let manager = self.get_global::<ZwpIdleInhibitManagerV1>().ok_or(MissingGlobal)?;
+        Ok(IdleInhibitor::new(&manager, surface))
+    }
+}
@@ -28,6 +28,9 @@
 
 #[macro_use]
 extern crate dlib;
+// used by the generated protocol bindings
+#[macro_use]
+extern crate bitflags;
 
 /// Re-exports of some crates, for convenience
 pub mod reexports {
@@ -38,10 +41,15 @@ pub mod reexports {
 }
 
 pub mod data_device;
+pub mod dmabuf;
 pub mod environment;
+pub mod gamma_control;
+pub mod idle_inhibit;
 mod lazy_global;
 pub mod output;
+pub mod output_management;
 pub mod primary_selection;
+pub mod protocols;
 pub mod seat;
 pub mod shell;
 pub mod shm;
@@ -53,7 +61,9 @@ mod surface;
 
 #[cfg(feature = "calloop")]
 pub use event_loop::WaylandSource;
-pub use surface::{get_surface_outputs, get_surface_scale_factor};
+pub use surface::{
+    get_surface_outputs, get_surface_scale_factor, get_surface_transform, BufferMapping, Damage,
+};
 
 #[macro_export]
 /// Declare a batteries-included SCTK environment
@@ -68,9 +78,15 @@ pub use surface::{get_surface_outputs, get_surface_scale_factor};
 /// - `wl_compositor` as a [`SimpleGlobal`](environment/struct.SimpleGlobal.html)
 /// - `wl_data_device_manager` as a [`DataDeviceHandler`](data_device/struct.DataDeviceHandler.html)
 /// - `wl_output` with the [`OutputHandler`](output/struct.OutputHandler.html)
+/// - `zxdg_output_manager_v1` with the [`XdgOutputHandler`](output/struct.XdgOutputHandler.html)
 /// - `wl_seat` with the [`SeatHandler`](seat/struct.SeatHandler.html)
 /// - `wl_subcompositor` as a [`SimpleGlobal`](environment/struct.SimpleGlobal.html)
 /// - `wl_shm` as a [`ShmHandler`](shm/struct.ShmHandler.html)
+/// - `zwp_linux_dmabuf_v1` as a [`DmabufHandler`](dmabuf/struct.DmabufHandler.html)
+/// - `wp_cursor_shape_manager_v1` as a [`SimpleGlobal`](environment/struct.SimpleGlobal.html)
+/// - `zwp_idle_inhibit_manager_v1` as a [`SimpleGlobal`](environment/struct.SimpleGlobal.html)
+/// - `wp_viewporter` as a [`SimpleGlobal`](environment/struct.SimpleGlobal.html)
+/// - `wp_single_pixel_buffer_manager_v1` as a [`SimpleGlobal`](environment/struct.SimpleGlobal.html)
 /// - `zwp` and `gtk` primary selection device manager as a [`PrimarySelectionHandler`](primary_selection/struct.PrimarySelectionHandler.html)
 ///
 /// If you don't need to add anything more, using it is as simple as:
@@ -111,8 +127,8 @@ pub use surface::{get_surface_outputs, get_surface_scale_factor};
 macro_rules! default_environment {
     ($env_name:ident, desktop
         $(,fields = [$($fname:ident : $fty:ty),* $(,)?])?
-        $(,singles = [$($sty:ty => $sname: ident),* $(,)?])?
-        $(,multis = [$($mty:ty => $mname:ident),* $(,)?])?
+        $(,singles = [$($sty:ty => $sname:ident $(@ $srange:expr)?),* $(,)?])?
+        $(,multis = [$($mty:ty => $mname:ident $(@ $mrange:expr)?),* $(,)?])?
         $(,)?
     ) => {
         $crate::default_environment!($env_name,
@@ -132,9 +148,9 @@ macro_rules! default_environment {
                 // decoration
                 $crate::reexports::protocols::unstable::xdg_decoration::v1::client::zxdg_decoration_manager_v1::ZxdgDecorationManagerV1 => sctk_decoration_mgr,
                 // others
-                $($($sty => $sname,)*)?
+                $($($sty => $sname $(@ $srange)?,)*)?
             ],
-            multis = [ $($($mty => $mname,)*)?  ],
+            multis = [ $($($mty => $mname $(@ $mrange)?,)*)?  ],
         );
 
         // Shell utility
@@ -146,8 +162,8 @@ macro_rules! default_environment {
     };
     ($env_name:ident
         $(,fields = [$($fname:ident : $fty:ty),* $(,)?])?
-        $(,singles = [$($sty:ty => $sname:ident),* $(,)?])?
-        $(,multis = [$($mty:ty => $mname:ident),* $(,)?])?
+        $(,singles = [$($sty:ty => $sname:ident $(@ $srange:expr)?),* $(,)?])?
+        $(,multis = [$($mty:ty => $mname:ident $(@ $mrange:expr)?),* $(,)?])?
         $(,)?
     ) => {
         /*
@@ -157,10 +173,17 @@ macro_rules! default_environment {
             // SimpleGlobals
             sctk_compositor: $crate::environment::SimpleGlobal<$crate::reexports::client::protocol::wl_compositor::WlCompositor>,
             sctk_subcompositor: $crate::environment::SimpleGlobal<$crate::reexports::client::protocol::wl_subcompositor::WlSubcompositor>,
+            sctk_cursor_shape_mgr: $crate::environment::SimpleGlobal<$crate::protocols::staging::cursor_shape::v1::client::wp_cursor_shape_manager_v1::WpCursorShapeManagerV1>,
+            sctk_idle_inhibit_mgr: $crate::environment::SimpleGlobal<$crate::reexports::protocols::unstable::idle_inhibit::v1::client::zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1>,
+            sctk_viewporter: $crate::environment::SimpleGlobal<$crate::reexports::protocols::viewporter::client::wp_viewporter::WpViewporter>,
+            sctk_single_pixel_buffer_mgr: $crate::environment::SimpleGlobal<$crate::protocols::staging::single_pixel_buffer::v1::client::wp_single_pixel_buffer_manager_v1::WpSinglePixelBufferManagerV1>,
             // shm
             sctk_shm: $crate::shm::ShmHandler,
+            // dmabuf
+            sctk_dmabuf: $crate::dmabuf::DmabufHandler,
             // output
             sctk_outputs: $crate::output::OutputHandler,
+            sctk_xdg_outputs: $crate::output::XdgOutputHandler,
             // seat
             sctk_seats: $crate::seat::SeatHandler,
             // data device
@@ -180,6 +203,17 @@ macro_rules! default_environment {
             }
         }
 
+        // Dmabuf utility
+        impl $crate::dmabuf::DmabufHandling for $env_name {
+            fn dmabuf_formats(&self) -> Vec<$crate::dmabuf::DmabufFormat> {
+                self.sctk_dmabuf.dmabuf_formats()
+            }
+
+            fn dmabuf_feedback(&self) -> Option<$crate::dmabuf::DmabufFeedback> {
+                self.sctk_dmabuf.dmabuf_feedback()
+            }
+        }
+
         // Seat utility
         impl $crate::seat::SeatHandling for $env_name {
             fn listen<F>(&mut self, f: F) -> $crate::seat::SeatListener
@@ -191,6 +225,16 @@ macro_rules! default_environment {
             {
                 self.sctk_seats.listen(f)
             }
+
+            fn listen_for_released_devices<F>(&mut self, f: F) -> $crate::seat::DeviceListener
+            where F: FnMut(
+                $crate::reexports::client::Attached<$crate::reexports::client::protocol::wl_seat::WlSeat>,
+                $crate::seat::SeatDevice,
+                $crate::reexports::client::DispatchData
+            ) + 'static
+            {
+                self.sctk_seats.listen_for_released_devices(f)
+            }
         }
 
         // Output utility
@@ -204,6 +248,21 @@ macro_rules! default_environment {
             {
                 self.sctk_outputs.listen(f)
             }
+
+            fn output_layout(&self) -> $crate::output::OutputLayout {
+                self.sctk_outputs.output_layout()
+            }
+
+            fn listen_for_changes<F>(&mut self, f: F) -> $crate::output::OutputChangeListener
+            where F: FnMut(
+                $crate::reexports::client::protocol::wl_output::WlOutput,
+                &$crate::output::OutputInfo,
+                $crate::output::OutputChange,
+                $crate::reexports::client::DispatchData,
+            ) + 'static
+            {
+                self.sctk_outputs.listen_for_changes(f)
+            }
         }
 
         // Data device utility
@@ -252,15 +311,23 @@ macro_rules! default_environment {
                 // SimpleGlobals
                 $crate::reexports::client::protocol::wl_compositor::WlCompositor => sctk_compositor,
                 $crate::reexports::client::protocol::wl_subcompositor::WlSubcompositor => sctk_subcompositor,
+                $crate::protocols::staging::cursor_shape::v1::client::wp_cursor_shape_manager_v1::WpCursorShapeManagerV1 => sctk_cursor_shape_mgr,
+                $crate::reexports::protocols::unstable::idle_inhibit::v1::client::zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1 => sctk_idle_inhibit_mgr,
+                $crate::reexports::protocols::viewporter::client::wp_viewporter::WpViewporter => sctk_viewporter,
+                $crate::protocols::staging::single_pixel_buffer::v1::client::wp_single_pixel_buffer_manager_v1::WpSinglePixelBufferManagerV1 => sctk_single_pixel_buffer_mgr,
                 // shm
                 $crate::reexports::client::protocol::wl_shm::WlShm => sctk_shm,
+                // dmabuf
+                $crate::protocols::unstable::linux_dmabuf::v1::client::zwp_linux_dmabuf_v1::ZwpLinuxDmabufV1 => sctk_dmabuf,
+                // output
+                $crate::reexports::protocols::unstable::xdg_output::v1::client::zxdg_output_manager_v1::ZxdgOutputManagerV1 => sctk_xdg_outputs,
                 // data device
                 $crate::reexports::client::protocol::wl_data_device_manager::WlDataDeviceManager => sctk_data_device_manager,
                 // primary selection
                 $crate::reexports::protocols::unstable::primary_selection::v1::client::zwp_primary_selection_device_manager_v1::ZwpPrimarySelectionDeviceManagerV1 => sctk_primary_selection_manager,
                 $crate::reexports::protocols::misc::gtk_primary_selection::client::gtk_primary_selection_device_manager::GtkPrimarySelectionDeviceManager => sctk_primary_selection_manager,
                 // user added
-                $($($sty => $sname),*)?
+                $($($sty => $sname $(@ $srange)?),*)?
             ],
             multis = [
                 // output globals
@@ -268,7 +335,7 @@ macro_rules! default_environment {
                 // seat globals
                 $crate::reexports::client::protocol::wl_seat::WlSeat => sctk_seats,
                 // user added
-                $($($mty => $mname),*)?
+                $($($mty => $mname $(@ $mrange)?),*)?
             ]
         );
     };
@@ -322,14 +389,33 @@ macro_rules! default_environment {
 ///     ]
 /// ).expect("Initial roundtrips failed!");
 /// ```
+///
+/// If your event loop must not block during the initialization, you can additionally add the
+/// `ready` argument, providing a closure that is invoked with the initialized `Environment` and
+/// the `DispatchData` while the events are dispatched, see
+/// [`Environment::new_async`](environment/struct.Environment.html#method.new_async). In that
+/// case the macro evaluates to the `Environment` right away, which can be used to setup your
+/// event sources but whose globals are not available yet.
+///
+/// ```no_run
+/// # use smithay_client_toolkit::{default_environment, new_default_environment};
+/// # default_environment!(MyEnv, desktop);
+/// # let display = smithay_client_toolkit::reexports::client::Display::connect_to_env().unwrap();
+/// # let queue = display.create_event_queue();
+/// let env = new_default_environment!(MyEnv,
+///     desktop,
+///     with=(display, queue),
+///     ready=|env, _| println!("{} outputs", env.get_all_outputs().len()),
+/// );
+/// ```
 macro_rules! new_default_environment {
     ($env_name:ident, desktop
-        $(, with=($display:expr, $queue:expr))?
+        $(, with=($display:expr, $queue:expr) $(, ready=$ready:expr)?)?
         $(,fields = [$($fname:ident : $fval:expr),* $(,)?])?
         $(,)?
     ) => {
         $crate::new_default_environment!($env_name,
-            $(with=($display, $queue),)?
+            $(with=($display, $queue), $(ready=$ready,)?)?
             fields = [
                 sctk_shell: $crate::shell::ShellHandler::new(),
                 sctk_decoration_mgr: $crate::environment::SimpleGlobal::new(),
@@ -339,28 +425,71 @@ macro_rules! new_default_environment {
             ]
         )
     };
-    ($env_name:ident, with=($display:expr, $queue:expr)
+    (@inner $env_name:ident
         $(,fields = [$($fname:ident : $fval:expr),* $(,)?])?
-        $(,)?
     ) => {
         {
             let mut sctk_seats = $crate::seat::SeatHandler::new();
+            let (sctk_outputs, sctk_xdg_outputs) = $crate::output::XdgOutputHandler::new_output_handlers();
             let sctk_data_device_manager = $crate::data_device::DataDeviceHandler::init(&mut sctk_seats);
             let sctk_primary_selection_manager = $crate::primary_selection::PrimarySelectionHandler::init(&mut sctk_seats);
 
-            let display = $crate::reexports::client::Proxy::clone(&$display);
-            let env = $crate::environment::Environment::new(&display.attach($queue.token()), &mut $queue,$env_name {
+            $env_name {
                 sctk_compositor: $crate::environment::SimpleGlobal::new(),
                 sctk_subcompositor: $crate::environment::SimpleGlobal::new(),
+                sctk_cursor_shape_mgr: $crate::environment::SimpleGlobal::new(),
+                sctk_idle_inhibit_mgr: $crate::environment::SimpleGlobal::new(),
+                sctk_viewporter: $crate::environment::SimpleGlobal::new(),
+                sctk_single_pixel_buffer_mgr: $crate::environment::SimpleGlobal::new(),
                 sctk_shm: $crate::shm::ShmHandler::new(),
-                sctk_outputs: $crate::output::OutputHandler::new(),
+                sctk_dmabuf: $crate::dmabuf::DmabufHandler::new(),
+                sctk_outputs,
+                sctk_xdg_outputs,
                 sctk_seats,
                 sctk_data_device_manager,
                 sctk_primary_selection_manager,
                 $($(
                     $fname: $fval,
                 )*)?
-            });
+            }
+        }
+    };
+    ($env_name:ident, with=($display:expr, $queue:expr), ready=$ready:expr
+        $(,fields = [$($fname:ident : $fval:expr),* $(,)?])?
+        $(,)?
+    ) => {
+        {
+            // gives its type to the closure
+            fn ready_callback<F>(f: F) -> F
+            where
+                F: FnOnce($crate::environment::Environment<$env_name>, $crate::reexports::client::DispatchData) + 'static,
+            {
+                f
+            }
+            let ready = ready_callback($ready);
+            let display = $crate::reexports::client::Proxy::clone(&$display);
+            $crate::environment::Environment::new_async(
+                &display.attach($queue.token()),
+                $crate::new_default_environment!(@inner $env_name, fields=[$($($fname: $fval),*)?]),
+                move |env: $crate::environment::Environment<$env_name>, ddata: $crate::reexports::client::DispatchData| {
+                    // Bind primary selection manager.
+                    let _psm = env.get_primary_selection_manager();
+                    ready(env, ddata)
+                },
+            )
+        }
+    };
+    ($env_name:ident, with=($display:expr, $queue:expr)
+        $(,fields = [$($fname:ident : $fval:expr),* $(,)?])?
+        $(,)?
+    ) => {
+        {
+            let display = $crate::reexports::client::Proxy::clone(&$display);
+            let env = $crate::environment::Environment::new(
+                &display.attach($queue.token()),
+                &mut $queue,
+                $crate::new_default_environment!(@inner $env_name, fields=[$($($fname: $fval),*)?]),
+            );
 
             if let Ok(env) = env.as_ref() {
                 // Bind primary selection manager.
@@ -32,7 +32,7 @@ use wayland_protocols::unstable::xdg_output::v1::client::{
 pub use wayland_client::protocol::wl_output::{Subpixel, Transform};
 
 /// A possible mode for an output
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Mode {
     /// Number of pixels of this mode in format `(width, height)`
     ///
@@ -67,10 +67,10 @@ pub struct OutputInfo {
     /// not assume that the name is a reflection of an underlying DRM connector,
     /// X11 connection, etc.
     ///
-    /// Note that this is not filled in by version 3 of the wl_output protocol,
-    /// but it has been proposed for inclusion in version 4.  Until then, it is
-    /// only filled in if your environment has an [XdgOutputHandler] global
-    /// handler for [ZxdgOutputManagerV1].
+    /// This is filled in by version 4 of the wl_output protocol. With older
+    /// compositors, it is only filled in if your environment has an
+    /// [XdgOutputHandler] global handler for [ZxdgOutputManagerV1], which is the
+    /// case of [`default_environment!`](../macro.default_environment.html).
     pub name: String,
     /// The description of this output as advertised by the server
     ///
@@ -79,10 +79,10 @@ pub struct OutputInfo {
     /// wl_output globals. Examples might include 'Foocorp 11" Display' or
     /// 'Virtual X11 output via :1'.
     ///
-    /// Note that this is not filled in by version 3 of the wl_output protocol,
-    /// but it has been proposed for inclusion in version 4.  Until then, it is
-    /// only filled in if your environment has an [XdgOutputHandler] global
-    /// handler for [ZxdgOutputManagerV1].
+    /// This is filled in by version 4 of the wl_output protocol. With older
+    /// compositors, it is only filled in if your environment has an
+    /// [XdgOutputHandler] global handler for [ZxdgOutputManagerV1], which is the
+    /// case of [`default_environment!`](../macro.default_environment.html).
     pub description: String,
     /// Location of the top-left corner of this output in compositor
     /// space
@@ -97,8 +97,10 @@ pub struct OutputInfo {
     /// The current transformation applied to this output
     ///
     /// You can pre-render your buffers taking this information
-    /// into account and advertising it via `wl_buffer.set_tranform`
-    /// for better performances.
+    /// into account and advertising it via `wl_surface.set_buffer_transform`
+    /// for better performances. See
+    /// [`get_surface_transform`](../fn.get_surface_transform.html) and
+    /// [`BufferMapping`](../struct.BufferMapping.html).
     pub transform: Transform,
     /// The scaling factor of this output
     ///
@@ -116,6 +118,19 @@ pub struct OutputInfo {
     /// call its `release()` method if you don't plan to use it any
     /// longer.
     pub obsolete: bool,
+    /// Location of the top-left corner of this output in the global compositor space,
+    /// in logical coordinates
+    ///
+    /// This is provided by the [XdgOutputHandler] when available, and is the `location`
+    /// of the output otherwise.
+    pub logical_position: (i32, i32),
+    /// Size of this output in the global compositor space, in logical coordinates
+    ///
+    /// This is provided by the [XdgOutputHandler] when available, and is otherwise
+    /// derived from the current mode, scale factor and transform of the output.
+    pub logical_size: (i32, i32),
+    // whether the logical geometry was provided by xdg-output
+    logical_from_xdg: bool,
 }
 
 impl OutputInfo {
@@ -133,6 +148,27 @@ impl OutputInfo {
             scale_factor: 1,
             modes: Vec::new(),
             obsolete: false,
+            logical_position: (0, 0),
+            logical_size: (0, 0),
+            logical_from_xdg: false,
+        }
+    }
+
+    // Derive the logical geometry from the core wl_output information
+    fn derive_logical_geometry(&mut self) {
+        if self.logical_from_xdg {
+            return;
+        }
+        self.logical_position = self.location;
+        if let Some(mode) = self.modes.iter().find(|m| m.is_current) {
+            let scale = std::cmp::max(self.scale_factor, 1);
+            let (width, height) = (mode.dimensions.0 / scale, mode.dimensions.1 / scale);
+            self.logical_size = match self.transform {
+                Transform::_90 | Transform::_270 | Transform::Flipped90 | Transform::Flipped270 => {
+                    (height, width)
+                }
+                _ => (width, height),
+            };
         }
     }
 }
@@ -158,6 +194,98 @@ enum OutputData {
 
 type OutputStatusCallback = dyn FnMut(WlOutput, &OutputInfo, DispatchData) + 'static;
 
+type OutputChangeCallback = dyn FnMut(WlOutput, &OutputInfo, OutputChange, DispatchData) + 'static;
+
+type ChangeListeners = Rc<RefCell<Vec<rc::Weak<RefCell<OutputChangeCallback>>>>>;
+
+/// A change of an output
+///
+/// These are computed by comparing the information of an output each time the compositor
+/// finishes sending changes to it with the previous one, see
+/// [`Environment::listen_for_output_changes`](../environment/struct.Environment.html#method.listen_for_output_changes).
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum OutputChange {
+    /// The output was advertised by the compositor
+    Added,
+    /// The output was removed by the compositor
+    Removed,
+    /// The scale factor of the output changed
+    ScaleChanged {
+        /// The previous scale factor
+        old: i32,
+        /// The new scale factor
+        new: i32,
+    },
+    /// The current mode of the output changed
+    ModeChanged {
+        /// The previous current mode
+        old: Option<Mode>,
+        /// The new current mode
+        new: Option<Mode>,
+    },
+    /// The transform of the output changed
+    TransformChanged {
+        /// The previous transform
+        old: Transform,
+        /// The new transform
+        new: Transform,
+    },
+    /// The logical position or size of the output changed
+    GeometryChanged {
+        /// The previous `(x, y, width, height)` logical geometry
+        old: (i32, i32, i32, i32),
+        /// The new `(x, y, width, height)` logical geometry
+        new: (i32, i32, i32, i32),
+    },
+    /// The name or description of the output changed
+    NameChanged {
+        /// The previous `(name, description)`
+        old: (String, String),
+        /// The new `(name, description)`
+        new: (String, String),
+    },
+}
+
+impl OutputChange {
+    /// Compute the changes between two states of the same output
+    ///
+    /// `old` is `None` if the output was not known before.
+    pub fn between(old: Option<&OutputInfo>, new: &OutputInfo) -> Vec<OutputChange> {
+        let old = match old {
+            _ if new.obsolete => return vec![OutputChange::Removed],
+            Some(old) => old,
+            None => return vec![OutputChange::Added],
+        };
+        let mut changes = Vec::new();
+        if old.scale_factor != new.scale_factor {
+            changes
+                .push(OutputChange::ScaleChanged { old: old.scale_factor, new: new.scale_factor });
+        }
+        let current_mode = |info: &OutputInfo| info.modes.iter().find(|m| m.is_current).copied();
+        if current_mode(old) != current_mode(new) {
+            changes
+                .push(OutputChange::ModeChanged { old: current_mode(old), new: current_mode(new) });
+        }
+        if old.transform != new.transform {
+            changes.push(OutputChange::TransformChanged { old: old.transform, new: new.transform });
+        }
+        if logical_rect(old) != logical_rect(new) {
+            changes.push(OutputChange::GeometryChanged {
+                old: logical_rect(old),
+                new: logical_rect(new),
+            });
+        }
+        if old.name != new.name || old.description != new.description {
+            changes.push(OutputChange::NameChanged {
+                old: (old.name.clone(), old.description.clone()),
+                new: (new.name.clone(), new.description.clone()),
+            });
+        }
+        changes
+    }
+}
+
 /// A handler for `wl_output`
 ///
 /// This handler can be used for managing `wl_output` in the
@@ -167,9 +295,11 @@ type OutputStatusCallback = dyn FnMut(WlOutput, &OutputInfo, DispatchData) + 'st
 /// It aggregates the output information and makes it available via the
 /// [`with_output_info`](fn.with_output_info.html) function.
 pub struct OutputHandler {
-    outputs: Vec<(u32, Attached<WlOutput>)>,
+    outputs: Vec<(u32, Attached<WlOutput>, OutputListener)>,
     status_listeners: Rc<RefCell<Vec<rc::Weak<RefCell<OutputStatusCallback>>>>>,
     xdg_listener: Option<rc::Weak<RefCell<XdgOutputHandlerInner>>>,
+    layout: Arc<Mutex<OutputLayout>>,
+    change_listeners: ChangeListeners,
 }
 
 impl OutputHandler {
@@ -179,6 +309,8 @@ impl OutputHandler {
             outputs: Vec::new(),
             status_listeners: Rc::new(RefCell::new(Vec::new())),
             xdg_listener: None,
+            layout: Arc::new(Mutex::new(OutputLayout { outputs: Vec::new() })),
+            change_listeners: Rc::new(RefCell::new(Vec::new())),
         }
     }
 }
@@ -191,8 +323,8 @@ impl crate::environment::MultiGlobalHandler<WlOutput> for OutputHandler {
         version: u32,
         _: DispatchData,
     ) {
-        // We currently support wl_output up to version 3
-        let version = std::cmp::min(version, 3);
+        // We currently support wl_output up to version 4
+        let version = std::cmp::min(version, 4);
         let output = registry.bind::<WlOutput>(version, id);
         let has_xdg;
         if let Some(xdg) = self.xdg_listener.as_ref().and_then(rc::Weak::upgrade) {
@@ -222,12 +354,34 @@ impl crate::environment::MultiGlobalHandler<WlOutput> for OutputHandler {
                 &xdg_listener_handle,
             )
         });
-        self.outputs.push((id, (*output).clone()));
+        // keep the layout up to date and compute the changes each time the info of this
+        // output is complete
+        let layout = self.layout.clone();
+        let previous = Mutex::new(None::<OutputInfo>);
+        // Use a UserData to safely share the change listeners with the other thread
+        let change_listeners = wayland_client::UserData::new();
+        let my_change_listeners = self.change_listeners.clone();
+        change_listeners.set(move || my_change_listeners);
+        let tracker = add_output_listener(&output, move |output, info, mut ddata| {
+            layout.lock().unwrap().update(output.clone(), info);
+            let changes = {
+                let mut previous = previous.lock().unwrap();
+                let changes = OutputChange::between(previous.as_ref(), info);
+                *previous = if info.obsolete { None } else { Some(info.clone()) };
+                changes
+            };
+            if let Some(listeners) = change_listeners.get::<ChangeListeners>() {
+                for change in changes {
+                    notify_change_listeners(&output, info, change, ddata.reborrow(), listeners);
+                }
+            }
+        });
+        self.outputs.push((id, (*output).clone(), tracker));
     }
     fn removed(&mut self, id: u32, mut ddata: DispatchData) {
         let status_listeners_handle = &self.status_listeners;
         let xdg_listener_handle = &self.xdg_listener;
-        self.outputs.retain(|(i, o)| {
+        self.outputs.retain(|(i, o, _)| {
             if *i != id {
                 true
             } else {
@@ -237,7 +391,7 @@ impl crate::environment::MultiGlobalHandler<WlOutput> for OutputHandler {
         });
     }
     fn get_all(&self) -> Vec<Attached<WlOutput>> {
-        self.outputs.iter().map(|(_, o)| o.clone()).collect()
+        self.outputs.iter().map(|(_, o, _)| o.clone()).collect()
     }
 }
 
@@ -247,6 +401,8 @@ impl fmt::Debug for OutputHandler {
             .field("outputs", &self.outputs)
             .field("status_listeners", &"Fn() -> { ... }")
             .field("xdg_listener", &self.xdg_listener)
+            .field("layout", &self.layout)
+            .field("change_listeners", &"Fn() -> { ... }")
             .finish()
     }
 }
@@ -362,6 +518,12 @@ fn merge_event(info: &mut OutputInfo, event: Event) {
         Event::Scale { factor } => {
             info.scale_factor = factor;
         }
+        Event::Name { name } => {
+            info.name = name;
+        }
+        Event::Description { description } => {
+            info.description = description;
+        }
         Event::Mode { width, height, refresh, flags } => {
             let mut found = false;
             if let Some(mode) = info
@@ -387,6 +549,7 @@ fn merge_event(info: &mut OutputInfo, event: Event) {
         // ignore all other events
         _ => (),
     }
+    info.derive_logical_geometry();
 }
 
 fn notify(
@@ -422,6 +585,23 @@ fn notify_status_listeners(
     })
 }
 
+fn notify_change_listeners(
+    output: &WlOutput,
+    info: &OutputInfo,
+    change: OutputChange,
+    mut ddata: DispatchData,
+    listeners: &RefCell<Vec<rc::Weak<RefCell<OutputChangeCallback>>>>,
+) {
+    // clone the list, so that listeners can be added from the callbacks
+    let current = listeners.borrow().clone();
+    for lst in current {
+        if let Some(cb) = rc::Weak::upgrade(&lst) {
+            (*cb.borrow_mut())(output.clone(), info, change.clone(), ddata.reborrow());
+        }
+    }
+    listeners.borrow_mut().retain(|lst| lst.strong_count() > 0);
+}
+
 /// Access the info associated with this output
 ///
 /// The provided closure is given the [`OutputInfo`](struct.OutputInfo.html) as argument,
@@ -501,6 +681,130 @@ impl fmt::Debug for OutputStatusListener {
     }
 }
 
+/// A handle to an output change callback
+///
+/// Dropping it disables the associated callback and frees the closure.
+pub struct OutputChangeListener {
+    _cb: Rc<RefCell<OutputChangeCallback>>,
+}
+
+impl fmt::Debug for OutputChangeListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OutputChangeListener").field("_cb", &"fn() -> { ... }").finish()
+    }
+}
+
+/// A direction in the output layout
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    /// Towards negative x
+    Left,
+    /// Towards positive x
+    Right,
+    /// Towards negative y
+    Above,
+    /// Towards positive y
+    Below,
+}
+
+/// A snapshot of the arrangement of the outputs in the global compositor space
+///
+/// It contains all the outputs whose information is complete, ordered by name, with their
+/// logical geometry. It is updated each time the compositor finishes sending changes to an
+/// output, so it never contains partial information; get a new snapshot from
+/// [`Environment::output_layout`](../environment/struct.Environment.html#method.output_layout)
+/// to see later changes.
+#[derive(Debug, Clone)]
+pub struct OutputLayout {
+    outputs: Vec<(WlOutput, OutputInfo)>,
+}
+
+// (x, y, width, height) of an output in the global compositor space
+fn logical_rect(info: &OutputInfo) -> (i32, i32, i32, i32) {
+    (info.logical_position.0, info.logical_position.1, info.logical_size.0, info.logical_size.1)
+}
+
+impl OutputLayout {
+    fn update(&mut self, output: WlOutput, info: &OutputInfo) {
+        self.outputs.retain(|(o, _)| o.as_ref().is_alive() && o != &output);
+        if !info.obsolete {
+            self.outputs.push((output, info.clone()));
+            self.outputs.sort_by(|(_, a), (_, b)| (&a.name, a.id).cmp(&(&b.name, b.id)));
+        }
+    }
+
+    /// The outputs of the layout, ordered by name
+    pub fn outputs(&self) -> &[(WlOutput, OutputInfo)] {
+        &self.outputs
+    }
+
+    /// The info of given output, if it is part of the layout
+    pub fn get(&self, output: &WlOutput) -> Option<&OutputInfo> {
+        self.outputs.iter().find(|(o, _)| o == output).map(|(_, info)| info)
+    }
+
+    /// The output containing given point, in logical coordinates
+    ///
+    /// If outputs overlap, the first one by name is returned.
+    pub fn output_at(&self, x: i32, y: i32) -> Option<&(WlOutput, OutputInfo)> {
+        self.outputs.iter().find(|(_, info)| {
+            let (ox, oy, w, h) = logical_rect(info);
+            x >= ox && y >= oy && x < ox + w && y < oy + h
+        })
+    }
+
+    /// The bounding box of all the outputs, as `(x, y, width, height)` in logical coordinates
+    ///
+    /// Returns `None` if the layout contains no output.
+    pub fn extents(&self) -> Option<(i32, i32, i32, i32)> {
+        let mut rects = self.outputs.iter().map(|(_, info)| logical_rect(info));
+        let (x, y, w, h) = rects.next()?;
+        let (mut x1, mut y1, mut x2, mut y2) = (x, y, x + w, y + h);
+        for (x, y, w, h) in rects {
+            x1 = x1.min(x);
+            y1 = y1.min(y);
+            x2 = x2.max(x + w);
+            y2 = y2.max(y + h);
+        }
+        Some((x1, y1, x2 - x1, y2 - y1))
+    }
+
+    /// The closest output in given direction from an output
+    ///
+    /// Only the outputs entirely on that side of `output` and overlapping it on the other
+    /// axis are considered. Among them, the closest one is returned, preferring the one
+    /// with the largest overlap.
+    pub fn neighbor(
+        &self,
+        output: &WlOutput,
+        direction: Direction,
+    ) -> Option<&(WlOutput, OutputInfo)> {
+        let (x, y, w, h) = logical_rect(self.get(output)?);
+        self.outputs
+            .iter()
+            .filter(|(o, _)| o != output)
+            .filter_map(|entry| {
+                let (ox, oy, ow, oh) = logical_rect(&entry.1);
+                let overlap = |a: i32, alen: i32, b: i32, blen: i32| {
+                    std::cmp::min(a + alen, b + blen) - std::cmp::max(a, b)
+                };
+                let (gap, overlap) = match direction {
+                    Direction::Left => (x - (ox + ow), overlap(y, h, oy, oh)),
+                    Direction::Right => (ox - (x + w), overlap(y, h, oy, oh)),
+                    Direction::Above => (y - (oy + oh), overlap(x, w, ox, ow)),
+                    Direction::Below => (oy - (y + h), overlap(x, w, ox, ow)),
+                };
+                if gap >= 0 && overlap > 0 {
+                    Some((gap, -overlap, entry))
+                } else {
+                    None
+                }
+            })
+            .min_by_key(|&(gap, overlap, _)| (gap, overlap))
+            .map(|(_, _, entry)| entry)
+    }
+}
+
 /// Trait representing the OutputHandler functions
 ///
 /// Implementing this trait on your inner environment struct used with the
@@ -513,6 +817,15 @@ pub trait OutputHandling {
         &mut self,
         f: F,
     ) -> OutputStatusListener;
+
+    /// A snapshot of the current output layout
+    fn output_layout(&self) -> OutputLayout;
+
+    /// Insert a listener for the changes of the outputs
+    fn listen_for_changes<F: FnMut(WlOutput, &OutputInfo, OutputChange, DispatchData) + 'static>(
+        &mut self,
+        f: F,
+    ) -> OutputChangeListener;
 }
 
 impl OutputHandling for OutputHandler {
@@ -524,6 +837,19 @@ impl OutputHandling for OutputHandler {
         self.status_listeners.borrow_mut().push(Rc::downgrade(&rc));
         OutputStatusListener { _cb: rc }
     }
+
+    fn output_layout(&self) -> OutputLayout {
+        self.layout.lock().unwrap().clone()
+    }
+
+    fn listen_for_changes<F: FnMut(WlOutput, &OutputInfo, OutputChange, DispatchData) + 'static>(
+        &mut self,
+        f: F,
+    ) -> OutputChangeListener {
+        let rc = Rc::new(RefCell::new(f)) as Rc<_>;
+        self.change_listeners.borrow_mut().push(Rc::downgrade(&rc));
+        OutputChangeListener { _cb: rc }
+    }
 }
 
 impl<E: OutputHandling> crate::environment::Environment<E> {
@@ -544,6 +870,31 @@ impl<E: OutputHandling> crate::environment::Environment<E> {
     ) -> OutputStatusListener {
         self.with_inner(move |inner| OutputHandling::listen(inner, f))
     }
+
+    /// Get a snapshot of the current output layout
+    ///
+    /// See [`OutputLayout`](../output/struct.OutputLayout.html) for details.
+    pub fn output_layout(&self) -> OutputLayout {
+        self.with_inner(|inner| OutputHandling::output_layout(inner))
+    }
+
+    /// Insert a new listener for the changes of the outputs
+    ///
+    /// The provided closure will be invoked with an [`OutputChange`](../output/enum.OutputChange.html)
+    /// for each change of an output, each time the compositor finishes sending changes to it,
+    /// including when it is added or removed.
+    ///
+    /// The returned [`OutputChangeListener`](../output/struct.OutputChangeListener.html) keeps your
+    /// callback alive, dropping it will disable it.
+    #[must_use = "the returned OutputChangeListener keeps your callback alive, dropping it will disable it"]
+    pub fn listen_for_output_changes<
+        F: FnMut(WlOutput, &OutputInfo, OutputChange, DispatchData) + 'static,
+    >(
+        &self,
+        f: F,
+    ) -> OutputChangeListener {
+        self.with_inner(move |inner| OutputHandling::listen_for_changes(inner, f))
+    }
 }
 
 impl<E: crate::environment::MultiGlobalHandler<WlOutput>> crate::environment::Environment<E> {
@@ -556,11 +907,12 @@ impl<E: crate::environment::MultiGlobalHandler<WlOutput>> crate::environment::En
 /// A handler for `zxdg_output_manager_v1`
 ///
 /// This handler adds additional information to the OutputInfo struct that is
-/// available through the xdg_output interface.  Because this requires binding
-/// the two handlers together when they are being created, it does not work with
-/// [`new_default_environment!`](../macro.new_default_environment.html); you
-/// must use [`default_environment!`](../macro.default_environment.html) and
-/// create the [OutputHandler] outside the constructor.
+/// available through the xdg_output interface, such as the name and description
+/// of outputs on compositors that do not support version 4 of `wl_output`.
+/// Because this requires binding the two handlers together when they are being
+/// created, you must create the [OutputHandler] with it if you declare your
+/// environment yourself. This is done for you by
+/// [`new_default_environment!`](../macro.new_default_environment.html).
 ///
 /// ```no_compile
 ///  let (sctk_outputs, sctk_xdg_out) = smithay_client_toolkit::output::XdgOutputHandler::new_output_handlers();
@@ -653,11 +1005,21 @@ fn process_xdg_event(
         OutputData::PendingXDG { info, callbacks } => (info, callbacks, true),
         OutputData::Pending { .. } => unreachable!(),
     };
+    // since version 4, the name and description are provided by the wl_output itself
+    let has_names = wl_out.as_ref().version() >= 4;
     match event {
-        Event::Name { name } => {
+        Event::LogicalPosition { x, y } => {
+            info.logical_position = (x, y);
+            info.logical_from_xdg = true;
+        }
+        Event::LogicalSize { width, height } => {
+            info.logical_size = (width, height);
+            info.logical_from_xdg = true;
+        }
+        Event::Name { name } if !has_names => {
             info.name = name;
         }
-        Event::Description { description } => {
+        Event::Description { description } if !has_names => {
             info.description = description;
         }
         Event::Done => {
@@ -0,0 +1,734 @@
+//! Output configuration through the wlr-output-management protocol
+//!
+//! Compositors based on wlroots let privileged clients like display-settings tools list
+//! the physical outputs, called heads, with their supported modes, and change their
+//! configuration through the `zwlr_output_manager_v1` global.
+//!
+//! The [`OutputManagementHandler`](struct.OutputManagementHandler.html) keeps track of the heads
+//! and their modes, and publishes a consistent snapshot of them as a list of
+//! [`HeadInfo`](struct.HeadInfo.html) each time the compositor is done sending changes. Changes
+//! are made by building an [`OutputConfiguration`](struct.OutputConfiguration.html) which is
+//! either tested or applied as a whole, and whose outcome is reported as a
+//! [`ConfigurationResult`](enum.ConfigurationResult.html).
+//!
+//! Enabled heads correspond to the `wl_output`s with the same name, see
+//! [`HeadInfo::matches_output`](struct.HeadInfo.html#method.matches_output).
+//!
+//! This handler is not included in the [`default_environment!`](../macro.default_environment.html),
+//! you need to add it to your environment and implement
+//! [`OutputManagementHandling`](trait.OutputManagementHandling.html) by delegating to it.
+
+use std::{
+    cell::RefCell,
+    fmt,
+    rc::{self, Rc},
+};
+
+use wayland_client::{
+    protocol::{wl_output, wl_registry},
+    Attached, DispatchData,
+};
+
+use crate::environment::{Environment, GlobalHandler, MultiGlobalHandler};
+use crate::output::{with_output_info, OutputInfo};
+use crate::MissingGlobal;
+use wayland_protocols::wlr::unstable::output_management::v1::client::{
+    zwlr_output_configuration_v1, zwlr_output_head_v1, zwlr_output_manager_v1, zwlr_output_mode_v1,
+};
+
+pub use wayland_protocols::wlr::unstable::output_management::v1::client::{
+    zwlr_output_head_v1::ZwlrOutputHeadV1, zwlr_output_manager_v1::ZwlrOutputManagerV1,
+    zwlr_output_mode_v1::ZwlrOutputModeV1,
+};
+
+/// A mode supported by a head
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeadMode {
+    /// The protocol object of this mode
+    pub mode: ZwlrOutputModeV1,
+    /// The size of the mode, in hardware units of the output device
+    pub dimensions: (i32, i32),
+    /// The refresh rate of the mode, in mHz, 0 if unknown
+    pub refresh_rate: i32,
+    /// Whether this is the preferred mode of the head
+    pub preferred: bool,
+}
+
+/// The state of a head, as advertised by the compositor
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct HeadInfo {
+    /// The protocol object of this head
+    pub head: ZwlrOutputHeadV1,
+    /// The name of the head, such as `HDMI-A-1`
+    ///
+    /// It is the same as the name of the matching `wl_output` when the head is enabled.
+    pub name: String,
+    /// A human-readable description of the head
+    pub description: String,
+    /// The manufacturer of the head, empty if unknown
+    pub make: String,
+    /// The model of the head, empty if unknown
+    pub model: String,
+    /// The serial number of the head, empty if unknown
+    pub serial_number: String,
+    /// The physical size of the head in millimeters, `(0, 0)` if unknown
+    pub physical_size: (i32, i32),
+    /// The modes supported by the head
+    ///
+    /// This can be empty for heads that only support custom modes, like virtual outputs.
+    pub modes: Vec<HeadMode>,
+    /// Whether the head is enabled
+    ///
+    /// The current mode, position, transform and scale are irrelevant for disabled heads.
+    pub enabled: bool,
+    /// The index of the current mode in `modes`
+    ///
+    /// This is `None` if the head is disabled or uses a custom mode.
+    pub current_mode: Option<usize>,
+    /// The position of the head in the global compositor space
+    pub position: (i32, i32),
+    /// The transform applied to the head
+    pub transform: wl_output::Transform,
+    /// The scale factor of the head
+    pub scale: f64,
+}
+
+impl HeadInfo {
+    /// The mode currently used by the head, if any
+    pub fn mode(&self) -> Option<&HeadMode> {
+        self.current_mode.and_then(|i| self.modes.get(i))
+    }
+
+    /// The preferred mode of the head, if any
+    pub fn preferred_mode(&self) -> Option<&HeadMode> {
+        self.modes.iter().find(|m| m.preferred)
+    }
+
+    /// Whether this head is the output described by given `OutputInfo`
+    ///
+    /// Heads and outputs are matched by name, so this requires the `name` of the
+    /// `OutputInfo` to be known, which needs version 4 of `wl_output` or the xdg-output
+    /// protocol. Disabled heads never match an output.
+    pub fn matches_output(&self, info: &OutputInfo) -> bool {
+        self.enabled && !self.name.is_empty() && self.name == info.name
+    }
+}
+
+// the state of a head while the compositor sends its changes
+#[derive(Debug)]
+struct PendingHead {
+    info: HeadInfo,
+    current_mode: Option<ZwlrOutputModeV1>,
+}
+
+#[derive(Debug)]
+struct PendingMode {
+    head: ZwlrOutputHeadV1,
+    mode: HeadMode,
+}
+
+type HeadsCallback = dyn FnMut(&[HeadInfo], DispatchData) + 'static;
+
+struct Inner {
+    manager: Option<Attached<ZwlrOutputManagerV1>>,
+    pending_heads: Vec<PendingHead>,
+    pending_modes: Vec<PendingMode>,
+    heads: Vec<HeadInfo>,
+    serial: Option<u32>,
+    listeners: Vec<rc::Weak<RefCell<HeadsCallback>>>,
+}
+
+impl Inner {
+    fn pending_head(&mut self, head: &ZwlrOutputHeadV1) -> Option<&mut PendingHead> {
+        self.pending_heads.iter_mut().find(|h| h.info.head == *head)
+    }
+
+    fn pending_mode(&mut self, mode: &ZwlrOutputModeV1) -> Option<&mut HeadMode> {
+        self.pending_modes.iter_mut().map(|m| &mut m.mode).find(|m| m.mode == *mode)
+    }
+
+    // publish the pending state of the heads
+    fn done(&mut self, serial: u32) {
+        let modes = &self.pending_modes;
+        self.heads = self
+            .pending_heads
+            .iter()
+            .map(|pending| {
+                let mut info = pending.info.clone();
+                info.modes =
+                    modes.iter().filter(|m| m.head == info.head).map(|m| m.mode.clone()).collect();
+                info.current_mode = match pending.current_mode {
+                    Some(ref current) if info.enabled => {
+                        info.modes.iter().position(|m| m.mode == *current)
+                    }
+                    _ => None,
+                };
+                info
+            })
+            .collect();
+        self.serial = Some(serial);
+    }
+}
+
+impl fmt::Debug for Inner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Inner")
+            .field("manager", &self.manager)
+            .field("pending_heads", &self.pending_heads)
+            .field("pending_modes", &self.pending_modes)
+            .field("heads", &self.heads)
+            .field("serial", &self.serial)
+            .field("listeners", &"Fn() -> { ... }")
+            .finish()
+    }
+}
+
+/// A handler for the `zwlr_output_manager_v1` global
+///
+/// It binds up to version 2 of the global and tracks the heads and modes advertised by
+/// the compositor.
+#[derive(Debug)]
+pub struct OutputManagementHandler {
+    inner: Rc<RefCell<Inner>>,
+}
+
+impl OutputManagementHandler {
+    /// Create a new OutputManagementHandler
+    pub fn new() -> OutputManagementHandler {
+        OutputManagementHandler {
+            inner: Rc::new(RefCell::new(Inner {
+                manager: None,
+                pending_heads: Vec::new(),
+                pending_modes: Vec::new(),
+                heads: Vec::new(),
+                serial: None,
+                listeners: Vec::new(),
+            })),
+        }
+    }
+}
+
+impl GlobalHandler<ZwlrOutputManagerV1> for OutputManagementHandler {
+    fn created(
+        &mut self,
+        registry: Attached<wl_registry::WlRegistry>,
+        id: u32,
+        version: u32,
+        _: DispatchData,
+    ) {
+        let version = std::cmp::min(version, 2);
+        let manager = registry.bind::<ZwlrOutputManagerV1>(version, id);
+        let inner = self.inner.clone();
+        manager.quick_assign(move |_, event, ddata| match event {
+            zwlr_output_manager_v1::Event::Head { head } => {
+                let inner_head = inner.clone();
+                head.quick_assign(move |head, event, _| {
+                    head_event(&inner_head, &head.detach(), event)
+                });
+                inner.borrow_mut().pending_heads.push(PendingHead {
+                    info: HeadInfo {
+                        head: head.detach(),
+                        name: String::new(),
+                        description: String::new(),
+                        make: String::new(),
+                        model: String::new(),
+                        serial_number: String::new(),
+                        physical_size: (0, 0),
+                        modes: Vec::new(),
+                        enabled: false,
+                        current_mode: None,
+                        position: (0, 0),
+                        transform: wl_output::Transform::Normal,
+                        scale: 1.0,
+                    },
+                    current_mode: None,
+                });
+            }
+            zwlr_output_manager_v1::Event::Done { serial } => {
+                inner.borrow_mut().done(serial);
+                notify_listeners(&inner, ddata);
+            }
+            zwlr_output_manager_v1::Event::Finished => {
+                {
+                    // the heads and modes are not valid anymore
+                    let mut inner = inner.borrow_mut();
+                    inner.manager = None;
+                    inner.serial = None;
+                    inner.heads.clear();
+                    inner.pending_heads.clear();
+                    inner.pending_modes.clear();
+                }
+                notify_listeners(&inner, ddata);
+            }
+            _ => {}
+        });
+        self.inner.borrow_mut().manager = Some((*manager).clone());
+    }
+
+    fn get(&self) -> Option<Attached<ZwlrOutputManagerV1>> {
+        self.inner.borrow().manager.clone()
+    }
+}
+
+fn notify_listeners(inner: &Rc<RefCell<Inner>>, mut ddata: DispatchData) {
+    let (heads, listeners) = {
+        let mut inner = inner.borrow_mut();
+        inner.listeners.retain(|lst| lst.upgrade().is_some());
+        let listeners: Vec<_> = inner.listeners.iter().filter_map(rc::Weak::upgrade).collect();
+        (inner.heads.clone(), listeners)
+    };
+    for listener in listeners {
+        (*listener.borrow_mut())(&heads, ddata.reborrow());
+    }
+}
+
+fn head_event(
+    inner: &Rc<RefCell<Inner>>,
+    head: &ZwlrOutputHeadV1,
+    event: zwlr_output_head_v1::Event,
+) {
+    use zwlr_output_head_v1::Event;
+    let mut guard = inner.borrow_mut();
+    match event {
+        Event::Mode { mode } => {
+            let inner_mode = inner.clone();
+            mode.quick_assign(move |mode, event, _| {
+                mode_event(&mut inner_mode.borrow_mut(), &mode.detach(), event)
+            });
+            guard.pending_modes.push(PendingMode {
+                head: head.clone(),
+                mode: HeadMode {
+                    mode: mode.detach(),
+                    dimensions: (0, 0),
+                    refresh_rate: 0,
+                    preferred: false,
+                },
+            });
+            return;
+        }
+        Event::Finished => {
+            guard.pending_heads.retain(|h| h.info.head != *head);
+            guard.pending_modes.retain(|m| m.head != *head);
+            return;
+        }
+        _ => {}
+    }
+    let pending = match guard.pending_head(head) {
+        Some(pending) => pending,
+        None => return,
+    };
+    match event {
+        Event::Name { name } => pending.info.name = name,
+        Event::Description { description } => pending.info.description = description,
+        Event::PhysicalSize { width, height } => pending.info.physical_size = (width, height),
+        Event::Enabled { enabled } => pending.info.enabled = enabled != 0,
+        Event::CurrentMode { mode } => pending.current_mode = Some(mode),
+        Event::Position { x, y } => pending.info.position = (x, y),
+        Event::Transform { transform } => pending.info.transform = transform,
+        Event::Scale { scale } => pending.info.scale = scale,
+        Event::Make { make } => pending.info.make = make,
+        Event::Model { model } => pending.info.model = model,
+        Event::SerialNumber { serial_number } => pending.info.serial_number = serial_number,
+        _ => {}
+    }
+}
+
+fn mode_event(inner: &mut Inner, mode: &ZwlrOutputModeV1, event: zwlr_output_mode_v1::Event) {
+    use zwlr_output_mode_v1::Event;
+    if let Event::Finished = event {
+        inner.pending_modes.retain(|m| m.mode.mode != *mode);
+        return;
+    }
+    let pending = match inner.pending_mode(mode) {
+        Some(pending) => pending,
+        None => return,
+    };
+    match event {
+        Event::Size { width, height } => pending.dimensions = (width, height),
+        Event::Refresh { refresh } => pending.refresh_rate = refresh,
+        Event::Preferred => pending.preferred = true,
+        _ => {}
+    }
+}
+
+/// The outcome of an [`OutputConfiguration`](struct.OutputConfiguration.html)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ConfigurationResult {
+    /// The configuration was applied, or would be applied if it was only tested
+    Succeeded,
+    /// The configuration was rejected by the compositor, the outputs were not changed
+    Failed,
+    /// The state of the heads changed since the configuration was created
+    ///
+    /// The configuration was discarded. Build a new one from the updated state of the heads
+    /// and try again.
+    Cancelled,
+}
+
+/// The mode to set on a head
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModeSetting {
+    /// One of the modes advertised for the head
+    Mode(ZwlrOutputModeV1),
+    /// A custom mode
+    Custom {
+        /// The width of the mode, in hardware units
+        width: i32,
+        /// The height of the mode, in hardware units
+        height: i32,
+        /// The refresh rate of the mode in mHz, or 0 to let the compositor choose
+        refresh_rate: i32,
+    },
+}
+
+/// The configuration of an enabled head
+///
+/// The properties that are not set are left for the compositor to choose.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HeadConfiguration {
+    mode: Option<ModeSetting>,
+    position: Option<(i32, i32)>,
+    transform: Option<wl_output::Transform>,
+    scale: Option<f64>,
+}
+
+impl HeadConfiguration {
+    /// A configuration that sets none of the properties of the head
+    pub fn new() -> HeadConfiguration {
+        HeadConfiguration::default()
+    }
+
+    /// A configuration keeping the current properties of a head
+    ///
+    /// This is the same as `new()` for disabled heads.
+    pub fn from_head(head: &HeadInfo) -> HeadConfiguration {
+        if !head.enabled {
+            return HeadConfiguration::new();
+        }
+        HeadConfiguration {
+            mode: head.mode().map(|m| ModeSetting::Mode(m.mode.clone())),
+            position: Some(head.position),
+            transform: Some(head.transform),
+            scale: Some(head.scale),
+        }
+    }
+
+    /// Use one of the modes advertised for the head
+    pub fn mode(mut self, mode: &HeadMode) -> Self {
+        self.mode = Some(ModeSetting::Mode(mode.mode.clone()));
+        self
+    }
+
+    /// Use a custom mode
+    ///
+    /// `refresh_rate` is in mHz, 0 lets the compositor choose it.
+    pub fn custom_mode(mut self, width: i32, height: i32, refresh_rate: i32) -> Self {
+        self.mode = Some(ModeSetting::Custom { width, height, refresh_rate });
+        self
+    }
+
+    /// Set the position of the head in the global compositor space
+    pub fn position(mut self, x: i32, y: i32) -> Self {
+        self.position = Some((x, y));
+        self
+    }
+
+    /// Set the transform of the head
+    pub fn transform(mut self, transform: wl_output::Transform) -> Self {
+        self.transform = Some(transform);
+        self
+    }
+
+    /// Set the scale factor of the head
+    pub fn scale(mut self, scale: f64) -> Self {
+        self.scale = Some(scale);
+        self
+    }
+
+    /// The mode that will be set, if any
+    pub fn get_mode(&self) -> Option<&ModeSetting> {
+        self.mode.as_ref()
+    }
+
+    /// The position that will be set, if any
+    pub fn get_position(&self) -> Option<(i32, i32)> {
+        self.position
+    }
+
+    /// The transform that will be set, if any
+    pub fn get_transform(&self) -> Option<wl_output::Transform> {
+        self.transform
+    }
+
+    /// The scale factor that will be set, if any
+    pub fn get_scale(&self) -> Option<f64> {
+        self.scale
+    }
+}
+
+/// A new configuration of the heads
+///
+/// A configuration is a transaction: it lists which heads are enabled, with their properties,
+/// and which are disabled, and is then either [`test`](#method.test)ed or
+/// [`apply`](#method.apply)'d as a whole.
+///
+/// The configuration is tied to the state of the heads it was created for. If this state
+/// changes before the compositor handles it, the configuration is cancelled.
+#[derive(Debug)]
+pub struct OutputConfiguration {
+    manager: Attached<ZwlrOutputManagerV1>,
+    serial: u32,
+    heads: Vec<(ZwlrOutputHeadV1, Option<HeadConfiguration>)>,
+}
+
+impl OutputConfiguration {
+    /// Create an empty configuration
+    ///
+    /// `serial` is the serial of the `done` event of the state of the heads this configuration
+    /// is built from. All the heads should be configured, as the compositor may reject a
+    /// configuration that leaves some of them out.
+    pub fn new(manager: &Attached<ZwlrOutputManagerV1>, serial: u32) -> OutputConfiguration {
+        OutputConfiguration { manager: manager.clone(), serial, heads: Vec::new() }
+    }
+
+    /// Create a configuration keeping the current state of the given heads
+    ///
+    /// Enabled heads keep their current properties, see
+    /// [`HeadConfiguration::from_head`](struct.HeadConfiguration.html#method.from_head), and
+    /// disabled heads stay disabled.
+    pub fn from_heads(
+        manager: &Attached<ZwlrOutputManagerV1>,
+        serial: u32,
+        heads: &[HeadInfo],
+    ) -> OutputConfiguration {
+        let mut config = OutputConfiguration::new(manager, serial);
+        for head in heads {
+            let head_config =
+                if head.enabled { Some(HeadConfiguration::from_head(head)) } else { None };
+            config.heads.push((head.head.clone(), head_config));
+        }
+        config
+    }
+
+    /// The serial of the state of the heads this configuration was built for
+    pub fn serial(&self) -> u32 {
+        self.serial
+    }
+
+    /// Enable a head with given configuration
+    ///
+    /// This replaces any previous configuration of this head.
+    pub fn enable(self, head: &ZwlrOutputHeadV1, config: HeadConfiguration) -> Self {
+        self.set(head, Some(config))
+    }
+
+    /// Disable a head
+    ///
+    /// This replaces any previous configuration of this head.
+    pub fn disable(self, head: &ZwlrOutputHeadV1) -> Self {
+        self.set(head, None)
+    }
+
+    /// The configuration of a head, `None` if it is disabled or not configured
+    pub fn head(&self, head: &ZwlrOutputHeadV1) -> Option<&HeadConfiguration> {
+        self.heads.iter().find(|&(h, _)| h == head).and_then(|(_, config)| config.as_ref())
+    }
+
+    fn set(mut self, head: &ZwlrOutputHeadV1, config: Option<HeadConfiguration>) -> Self {
+        match self.heads.iter_mut().find(|(h, _)| h == head) {
+            Some(entry) => entry.1 = config,
+            None => self.heads.push((head.clone(), config)),
+        }
+        self
+    }
+
+    /// Check whether the compositor would accept this configuration, without applying it
+    ///
+    /// The callback is invoked with the outcome once the compositor has checked it.
+    pub fn test<F>(self, callback: F)
+    where
+        F: FnOnce(ConfigurationResult, DispatchData) + 'static,
+    {
+        self.send(false, callback)
+    }
+
+    /// Apply this configuration
+    ///
+    /// The callback is invoked with the outcome once the compositor has handled it. The
+    /// compositor may adjust some of the properties, for example by rounding the scale, the
+    /// resulting state is advertised as a new state of the heads.
+    pub fn apply<F>(self, callback: F)
+    where
+        F: FnOnce(ConfigurationResult, DispatchData) + 'static,
+    {
+        self.send(true, callback)
+    }
+
+    fn send<F>(self, apply: bool, callback: F)
+    where
+        F: FnOnce(ConfigurationResult, DispatchData) + 'static,
+    {
+        let configuration = self.manager.create_configuration(self.serial);
+        for (head, config) in &self.heads {
+            let config = match config {
+                Some(config) => config,
+                None => {
+                    configuration.disable_head(head);
+                    continue;
+                }
+            };
+            let config_head = configuration.enable_head(head);
+            match config.mode {
+                Some(ModeSetting::Mode(ref mode)) => config_head.set_mode(mode),
+                Some(ModeSetting::Custom { width, height, refresh_rate }) => {
+                    config_head.set_custom_mode(width, height, refresh_rate)
+                }
+                None => {}
+            }
+            if let Some((x, y)) = config.position {
+                config_head.set_position(x, y);
+            }
+            if let Some(transform) = config.transform {
+                config_head.set_transform(transform);
+            }
+            if let Some(scale) = config.scale {
+                config_head.set_scale(scale);
+            }
+        }
+        let mut callback = Some(callback);
+        configuration.quick_assign(move |configuration, event, ddata| {
+            let result = match event {
+                zwlr_output_configuration_v1::Event::Succeeded => ConfigurationResult::Succeeded,
+                zwlr_output_configuration_v1::Event::Failed => ConfigurationResult::Failed,
+                zwlr_output_configuration_v1::Event::Cancelled => ConfigurationResult::Cancelled,
+                _ => return,
+            };
+            configuration.destroy();
+            if let Some(callback) = callback.take() {
+                callback(result, ddata);
+            }
+        });
+        if apply {
+            configuration.apply();
+        } else {
+            configuration.test();
+        }
+    }
+}
+
+/// A handle to a head listener callback
+///
+/// Dropping it disables the associated callback and frees the closure.
+pub struct HeadListener {
+    _cb: Rc<RefCell<HeadsCallback>>,
+}
+
+impl fmt::Debug for HeadListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HeadListener").field("_cb", &"fn() -> { ... }").finish()
+    }
+}
+
+/// An interface trait to forward the output management handler capability
+///
+/// You need to implement this trait for your environment struct, by
+/// delegating it to its `OutputManagementHandler` field in order to get the
+/// associated methods on your [`Environment`](../environment/struct.environment.html).
+pub trait OutputManagementHandling {
+    /// The current state of the heads
+    fn heads(&self) -> Vec<HeadInfo>;
+    /// A configuration keeping the current state of the heads
+    fn output_configuration(&self) -> Option<OutputConfiguration>;
+    /// Insert a listener for the changes of the heads
+    fn listen_for_heads<F: FnMut(&[HeadInfo], DispatchData) + 'static>(
+        &mut self,
+        f: F,
+    ) -> HeadListener;
+}
+
+impl OutputManagementHandling for OutputManagementHandler {
+    fn heads(&self) -> Vec<HeadInfo> {
+        self.inner.borrow().heads.clone()
+    }
+
+    fn output_configuration(&self) -> Option<OutputConfiguration> {
+        let inner = self.inner.borrow();
+        match (&inner.manager, inner.serial) {
+            (Some(manager), Some(serial)) => {
+                Some(OutputConfiguration::from_heads(manager, serial, &inner.heads))
+            }
+            _ => None,
+        }
+    }
+
+    fn listen_for_heads<F: FnMut(&[HeadInfo], DispatchData) + 'static>(
+        &mut self,
+        f: F,
+    ) -> HeadListener {
+        let rc = Rc::new(RefCell::new(f)) as Rc<_>;
+        self.inner.borrow_mut().listeners.push(Rc::downgrade(&rc));
+        HeadListener { _cb: rc }
+    }
+}
+
+impl<E: OutputManagementHandling> Environment<E> {
+    /// Get the current state of the heads
+    ///
+    /// This is empty if the compositor does not support `zwlr_output_manager_v1`.
+    pub fn get_output_heads(&self) -> Vec<HeadInfo> {
+        self.with_inner(|inner| inner.heads())
+    }
+
+    /// Create a configuration keeping the current state of the heads
+    ///
+    /// Change it with [`OutputConfiguration::enable`](../output_management/struct.OutputConfiguration.html#method.enable)
+    /// and [`OutputConfiguration::disable`](../output_management/struct.OutputConfiguration.html#method.disable)
+    /// before testing or applying it.
+    ///
+    /// Returns an error if the compositor does not support `zwlr_output_manager_v1` or
+    /// has not sent the state of the heads yet.
+    pub fn create_output_configuration(&self) -> Result<OutputConfiguration, MissingGlobal> {
+        self.with_inner(|inner| inner.output_configuration()).ok_or(MissingGlobal)
+    }
+
+    /// Insert a new listener for the heads
+    ///
+    /// The provided closure will be invoked with the state of all the heads each time the
+    /// compositor is done sending changes to them, and with an empty list if the compositor
+    /// stops sending them.
+    ///
+    /// The returned [`HeadListener`](../output_management/struct.HeadListener.html) keeps your
+    /// callback alive, dropping it will disable it.
+    #[must_use = "the returned HeadListener keeps your callback alive, dropping it will disable it"]
+    pub fn listen_for_output_heads<F: FnMut(&[HeadInfo], DispatchData) + 'static>(
+        &self,
+        f: F,
+    ) -> HeadListener {
+        self.with_inner(move |inner| inner.listen_for_heads(f))
+    }
+
+    /// Get the head corresponding to given output
+    ///
+    /// Returns `None` if the output has no name or no enabled head has its name.
+    pub fn get_output_head(&self, output: &wl_output::WlOutput) -> Option<HeadInfo> {
+        let heads = self.get_output_heads();
+        with_output_info(output, |info| heads.into_iter().find(|h| h.matches_output(info)))
+            .and_then(|head| head)
+    }
+}
+
+impl<E> Environment<E>
+where
+    E: OutputManagementHandling + MultiGlobalHandler<wl_output::WlOutput>,
+{
+    /// Get the output corresponding to given head
+    ///
+    /// Returns `None` if the head is disabled or no output has its name.
+    pub fn get_head_output(&self, head: &HeadInfo) -> Option<wl_output::WlOutput> {
+        self.get_all_globals::<wl_output::WlOutput>()
+            .into_iter()
+            .map(|output| output.detach())
+            .find(|output| with_output_info(output, |info| head.matches_output(info)) == Some(true))
+    }
+}
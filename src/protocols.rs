@@ -0,0 +1,77 @@
+//! Protocol bindings generated by SCTK
+//!
+//! Some protocols SCTK supports are more recent than the `wayland-protocols` version
+//! it depends on. Their bindings are generated in this crate and made available here,
+//! with the same layout as in [`wayland-protocols`](../reexports/protocols/index.html).
+
+macro_rules! sctk_protocol(
+    ($name: expr, [$(($import: ident, $interface: ident)),*], [$(($($prot_path:ident)::+, $prot_import: ident)),*]) => {
+        pub use self::generated::client;
+
+        mod generated {
+            // generated code, we don't control what it looks like
+            #![allow(warnings, clippy::all)]
+
+            pub mod client {
+                //! Client-side API of this protocol
+                pub(crate) use wayland_client::{sys, AnonymousObject, Attached, Main, Proxy, ProxyMap};
+                pub(crate) use wayland_client::protocol::{$($import),*};
+                pub(crate) use wayland_commons::map::{Object, ObjectMetadata};
+                pub(crate) use wayland_commons::smallvec;
+                pub(crate) use wayland_commons::wire::{Argument, ArgumentType, Message, MessageDesc};
+                pub(crate) use wayland_commons::{Interface, MessageGroup};
+                $(
+                    pub(crate) use $($prot_path::)+client::$prot_import;
+                )*
+                include!(concat!(env!("OUT_DIR"), "/", $name, "_client_api.rs"));
+            }
+        }
+    }
+);
+
+/// Staging protocols
+pub mod staging {
+    /// Cursor shape protocol
+    ///
+    /// Allows clients to set the cursor of a pointer to a shape from a predefined
+    /// set, which the compositor then draws using the user's cursor theme.
+    pub mod cursor_shape {
+        /// Version 1 of the protocol
+        pub mod v1 {
+            sctk_protocol!(
+                "cursor-shape-v1",
+                [(wl_pointer, WlPointer)],
+                [(wayland_protocols::unstable::tablet::v2, zwp_tablet_tool_v2)]
+            );
+        }
+    }
+    /// Single-pixel buffer protocol
+    ///
+    /// Allows clients to create 1x1 buffers of a single colour, which can be scaled
+    /// to any size using the viewporter protocol.
+    pub mod single_pixel_buffer {
+        /// Version 1 of the protocol
+        pub mod v1 {
+            sctk_protocol!("single-pixel-buffer-v1", [(wl_buffer, WlBuffer)], []);
+        }
+    }
+}
+
+/// Unstable protocols
+pub mod unstable {
+    /// Linux DMA-BUF protocol
+    ///
+    /// Allows clients to create `wl_buffer`s from DMA-BUFs. This is version 4 of the
+    /// protocol, which adds the dmabuf feedback objects to the version 3 provided by
+    /// `wayland-protocols`.
+    pub mod linux_dmabuf {
+        /// Version 1 of the protocol
+        pub mod v1 {
+            sctk_protocol!(
+                "linux-dmabuf-unstable-v1",
+                [(wl_buffer, WlBuffer), (wl_surface, WlSurface)],
+                []
+            );
+        }
+    }
+}
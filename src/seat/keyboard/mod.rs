@@ -136,6 +136,9 @@ pub enum Event<'a> {
 /// Returns an error if xkbcommon could not be initialized, the RMLVO specification
 /// contained invalid values, or if the provided seat does not have keyboard capability.
 ///
+/// The keyboard is automatically released when the seat loses its keyboard capability
+/// or is removed.
+///
 /// **Note:** This adapter does not handle key repetition. See `map_keyboard_repeat` for that.
 pub fn map_keyboard<F>(
     seat: &Attached<wl_seat::WlSeat>,
@@ -168,6 +171,8 @@ where
         kbd_handler.event(keyboard.detach(), event, data)
     });
 
+    super::track_device(seat, super::SeatDevice::Keyboard(keyboard.detach()));
+
     Ok(keyboard.detach())
 }
 
@@ -182,6 +187,9 @@ where
 /// Returns an error if xkbcommon could not be initialized, the RMLVO specification
 /// contained invalid values, or if the provided seat does not have keyboard capability.
 ///
+/// The keyboard is automatically released when the seat loses its keyboard capability
+/// or is removed.
+///
 /// **Note:** The keyboard repetition handling requires the `calloop` cargo feature.
 #[cfg(feature = "calloop")]
 pub fn map_keyboard_repeat<F, Data: 'static>(
@@ -247,6 +255,8 @@ where
         kbd_handler.event(keyboard.detach(), event, data)
     });
 
+    super::track_device(seat, super::SeatDevice::Keyboard(keyboard.detach()));
+
     Ok((keyboard.detach(), source))
 }
 
@@ -9,6 +9,13 @@
 //! a new seat is created of the state of a seat changes, via the
 //! [`Environment::listen_for_seats`](../environment/struct.Environment.html) method.
 //!
+//! The keyboards, pointers and touch devices created by the helpers of this module (like
+//! [`keyboard::map_keyboard`](keyboard/fn.map_keyboard.html) or
+//! [`ThemeManager::theme_pointer_with_impl`](pointer/struct.ThemeManager.html#method.theme_pointer_with_impl))
+//! are owned by the `SeatHandler`: they are automatically released when their seat loses the
+//! associated capability or is removed, and you can be notified of it via the
+//! [`Environment::listen_for_released_devices`](../environment/struct.Environment.html) method.
+//!
 //! **Note:** if you don't use the [`default_environment!`](../macro.default_environment.html),
 //! you'll need to implement the [`SeatHandling`](trait.SeatHandling.hmtl) on your
 //! environment struct to access the added methods on
@@ -24,14 +31,18 @@ use std::{
 use bitflags::bitflags;
 
 use wayland_client::{
-    protocol::{wl_registry, wl_seat},
+    protocol::{wl_keyboard, wl_pointer, wl_registry, wl_seat, wl_touch},
     Attached, DispatchData, Main,
 };
 
 pub mod keyboard;
 pub mod pointer;
+mod state;
+
+pub use self::state::{SeatEvent, SeatState};
 
 type SeatCallback = dyn FnMut(Attached<wl_seat::WlSeat>, &SeatData, DispatchData) + 'static;
+type DeviceCallback = dyn FnMut(Attached<wl_seat::WlSeat>, SeatDevice, DispatchData) + 'static;
 
 /// The metadata associated with a seat
 #[derive(Clone)]
@@ -56,6 +67,8 @@ pub struct SeatData {
 
     /// State of readiness of the data.
     state: SeatDataState,
+    /// Devices created by SCTK for this seat
+    devices: Vec<SeatDevice>,
 }
 
 bitflags! {
@@ -88,10 +101,97 @@ impl SeatData {
             has_touch: false,
             defunct: false,
             state: SeatDataState::NEW,
+            devices: Vec::new(),
+        }
+    }
+
+    /// Remove from the tracked devices the ones whose capability is no longer available
+    fn take_withdrawn_devices(&mut self) -> Vec<SeatDevice> {
+        let (has_keyboard, has_pointer, has_touch, defunct) =
+            (self.has_keyboard, self.has_pointer, self.has_touch, self.defunct);
+        let mut withdrawn = Vec::new();
+        self.devices.retain(|device| {
+            let keep = !defunct
+                && match *device {
+                    SeatDevice::Keyboard(_) => has_keyboard,
+                    SeatDevice::Pointer(_) => has_pointer,
+                    SeatDevice::Touch(_) => has_touch,
+                };
+            if !keep {
+                withdrawn.push(device.clone());
+            }
+            keep
+        });
+        withdrawn
+    }
+}
+
+/// An input device of a seat
+///
+/// This is used to notify you that a device created by one of the helpers of this
+/// module has been released by SCTK.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SeatDevice {
+    /// A keyboard
+    Keyboard(wl_keyboard::WlKeyboard),
+    /// A pointer
+    Pointer(wl_pointer::WlPointer),
+    /// A touch device
+    Touch(wl_touch::WlTouch),
+}
+
+impl SeatDevice {
+    fn is_alive(&self) -> bool {
+        match *self {
+            SeatDevice::Keyboard(ref kbd) => kbd.as_ref().is_alive(),
+            SeatDevice::Pointer(ref ptr) => ptr.as_ref().is_alive(),
+            SeatDevice::Touch(ref touch) => touch.as_ref().is_alive(),
+        }
+    }
+
+    fn release(&self) {
+        // The release requests only exist since version 3 of `wl_seat`, on older versions
+        // the devices can't be destroyed, they just stop receiving events.
+        match *self {
+            SeatDevice::Keyboard(ref kbd) if kbd.as_ref().version() >= 3 => kbd.release(),
+            SeatDevice::Pointer(ref ptr) if ptr.as_ref().version() >= 3 => ptr.release(),
+            SeatDevice::Touch(ref touch) if touch.as_ref().version() >= 3 => touch.release(),
+            _ => {}
         }
     }
 }
 
+/// Let the `SeatHandler` take care of releasing a device created for this seat
+pub(crate) fn track_device(seat: &wl_seat::WlSeat, device: SeatDevice) {
+    if let Some(udata_mutex) = seat.as_ref().user_data().get::<Mutex<SeatData>>() {
+        udata_mutex.lock().unwrap().devices.push(device);
+    }
+}
+
+/// Release the devices that are no longer available and notify the listeners
+fn release_devices(
+    seat: &Attached<wl_seat::WlSeat>,
+    devices: Vec<SeatDevice>,
+    listeners: &RefCell<Vec<Weak<RefCell<DeviceCallback>>>>,
+    mut ddata: DispatchData,
+) {
+    for device in devices {
+        // The device may already have been released by the user
+        if !device.is_alive() {
+            continue;
+        }
+        device.release();
+        listeners.borrow_mut().retain(|lst| {
+            if let Some(cb) = Weak::upgrade(lst) {
+                (*cb.borrow_mut())(seat.clone(), device.clone(), ddata.reborrow());
+                true
+            } else {
+                false
+            }
+        });
+    }
+}
+
 /// A simple handler for seats
 ///
 /// This handler will manage seats and track their capabilities.
@@ -101,12 +201,17 @@ impl SeatData {
 pub struct SeatHandler {
     seats: Vec<(u32, Attached<wl_seat::WlSeat>)>,
     listeners: Rc<RefCell<Vec<Weak<RefCell<SeatCallback>>>>>,
+    device_listeners: Rc<RefCell<Vec<Weak<RefCell<DeviceCallback>>>>>,
 }
 
 impl SeatHandler {
     /// Create a new SeatHandler
     pub fn new() -> SeatHandler {
-        SeatHandler { seats: Vec::new(), listeners: Rc::new(RefCell::new(Vec::new())) }
+        SeatHandler {
+            seats: Vec::new(),
+            listeners: Rc::new(RefCell::new(Vec::new())),
+            device_listeners: Rc::new(RefCell::new(Vec::new())),
+        }
     }
 }
 
@@ -115,6 +220,7 @@ impl fmt::Debug for SeatHandler {
         f.debug_struct("SeatHandler")
             .field("seats", &self.seats)
             .field("listeners", &"Fn(..) -> { ... }")
+            .field("device_listeners", &"Fn(..) -> { ... }")
             .finish()
     }
 }
@@ -126,6 +232,13 @@ pub struct SeatListener {
     _cb: Rc<RefCell<SeatCallback>>,
 }
 
+/// A handle to a released device listener callback
+///
+/// Dropping it disables the associated callback and frees the closure.
+pub struct DeviceListener {
+    _cb: Rc<RefCell<DeviceCallback>>,
+}
+
 impl crate::environment::MultiGlobalHandler<wl_seat::WlSeat> for SeatHandler {
     fn created(
         &mut self,
@@ -139,25 +252,33 @@ impl crate::environment::MultiGlobalHandler<wl_seat::WlSeat> for SeatHandler {
         let seat = registry.bind::<wl_seat::WlSeat>(version, id);
         seat.as_ref().user_data().set_threadsafe(|| Mutex::new(SeatData::new()));
         let cb_listeners = self.listeners.clone();
+        let cb_device_listeners = self.device_listeners.clone();
         seat.quick_assign(move |seat, event, ddata| {
-            process_seat_event(seat, event, &cb_listeners, ddata)
+            process_seat_event(seat, event, &cb_listeners, &cb_device_listeners, ddata)
         });
         self.seats.push((id, (*seat).clone()));
     }
     fn removed(&mut self, id: u32, mut ddata: DispatchData) {
         let mut listeners = self.listeners.borrow_mut();
+        let device_listeners = &self.device_listeners;
         self.seats.retain(|&(i, ref seat)| {
             if i != id {
                 true
             } else {
                 // This data must be `Mutex<SeatData>` if this seat is in our vec
                 let data = seat.as_ref().user_data().get::<Mutex<SeatData>>().unwrap();
-                let mut guard = data.lock().unwrap();
-                guard.defunct = true;
+                let (new_data, withdrawn) = {
+                    let mut guard = data.lock().unwrap();
+                    guard.defunct = true;
+                    let withdrawn = guard.take_withdrawn_devices();
+                    (guard.clone(), withdrawn)
+                };
+                // release all the devices of this seat
+                release_devices(seat, withdrawn, device_listeners, ddata.reborrow());
                 // notify the listeners that the seat is dead
                 listeners.retain(|lst| {
                     if let Some(cb) = Weak::upgrade(lst) {
-                        (&mut *cb.borrow_mut())(seat.clone(), &*guard, ddata.reborrow());
+                        (*cb.borrow_mut())(seat.clone(), &new_data, ddata.reborrow());
                         true
                     } else {
                         false
@@ -178,13 +299,20 @@ impl fmt::Debug for SeatListener {
     }
 }
 
+impl fmt::Debug for DeviceListener {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeviceListener").field("_cb", &"Fn(..) -> { ... }").finish()
+    }
+}
+
 fn process_seat_event(
     seat: Main<wl_seat::WlSeat>,
     event: wl_seat::Event,
     listeners: &RefCell<Vec<Weak<RefCell<SeatCallback>>>>,
+    device_listeners: &RefCell<Vec<Weak<RefCell<DeviceCallback>>>>,
     mut ddata: DispatchData,
 ) {
-    let new_data = {
+    let (new_data, withdrawn) = {
         let data = seat.as_ref().user_data().get::<Mutex<SeatData>>().unwrap();
         let mut guard = data.lock().unwrap();
         match event {
@@ -200,9 +328,12 @@ fn process_seat_event(
             }
             _ => unreachable!(),
         }
-        guard.clone()
+        let withdrawn = guard.take_withdrawn_devices();
+        (guard.clone(), withdrawn)
     };
 
+    release_devices(&seat, withdrawn, device_listeners, ddata.reborrow());
+
     if new_data.state.contains(SeatDataState::READY) {
         listeners.borrow_mut().retain(|lst| {
             if let Some(cb) = Weak::upgrade(lst) {
@@ -262,6 +393,14 @@ pub trait SeatHandling {
         &mut self,
         f: F,
     ) -> SeatListener;
+
+    /// Insert a listener for the devices released by SCTK
+    fn listen_for_released_devices<
+        F: FnMut(Attached<wl_seat::WlSeat>, SeatDevice, DispatchData) + 'static,
+    >(
+        &mut self,
+        f: F,
+    ) -> DeviceListener;
 }
 
 impl SeatHandling for SeatHandler {
@@ -273,6 +412,17 @@ impl SeatHandling for SeatHandler {
         self.listeners.borrow_mut().push(Rc::downgrade(&rc));
         SeatListener { _cb: rc }
     }
+
+    fn listen_for_released_devices<
+        F: FnMut(Attached<wl_seat::WlSeat>, SeatDevice, DispatchData) + 'static,
+    >(
+        &mut self,
+        f: F,
+    ) -> DeviceListener {
+        let rc = Rc::new(RefCell::new(f)) as Rc<_>;
+        self.device_listeners.borrow_mut().push(Rc::downgrade(&rc));
+        DeviceListener { _cb: rc }
+    }
 }
 
 impl<E: SeatHandling> crate::environment::Environment<E> {
@@ -296,6 +446,27 @@ impl<E: SeatHandling> crate::environment::Environment<E> {
     ) -> SeatListener {
         self.with_inner(move |inner| SeatHandling::listen(inner, f))
     }
+
+    /// Insert a new listener for released devices
+    ///
+    /// The provided closure will be invoked whenever SCTK releases a keyboard, pointer or
+    /// touch device it created for a seat, because the seat lost the associated capability
+    /// or was removed. You can use it to cleanup any state associated with this device.
+    ///
+    /// The device is only released if the seat is at least version 3, on older seats
+    /// the device can't be destroyed and just stops receiving events.
+    ///
+    /// The returned [`DeviceListener`](../seat/struct.DeviceListener.hmtl) keeps your callback alive,
+    /// dropping it will disable it.
+    #[must_use = "the returned DeviceListener keeps your callback alive, dropping it will disable it"]
+    pub fn listen_for_released_devices<
+        F: FnMut(Attached<wl_seat::WlSeat>, SeatDevice, DispatchData) + 'static,
+    >(
+        &self,
+        f: F,
+    ) -> DeviceListener {
+        self.with_inner(move |inner| SeatHandling::listen_for_released_devices(inner, f))
+    }
 }
 
 impl<E: crate::environment::MultiGlobalHandler<wl_seat::WlSeat>>
@@ -0,0 +1,215 @@
+/// A cursor icon from the CSS cursor set
+///
+/// Cursor themes do not agree on the names of their cursor images: some only ship the
+/// legacy X11 names (`left_ptr`, `xterm`, `hand2`, ...) while more recent ones only use
+/// the names from the CSS specification (`default`, `text`, `pointer`, ...). Each variant
+/// of this enum carries a list of names that are tried in order when loading the icon
+/// from a theme, so that the same icon can be found on both kinds of themes.
+///
+/// See [`ThemedPointer::set_cursor_icon`](struct.ThemedPointer.html#method.set_cursor_icon).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CursorIcon {
+    /// The platform-dependent default cursor, typically an arrow
+    Default,
+    /// A context menu is available for the object under the cursor
+    ContextMenu,
+    /// Help is available for the object under the cursor
+    Help,
+    /// The cursor is a pointer that indicates a link
+    Pointer,
+    /// The program is busy in the background but the user can still interact with it
+    Progress,
+    /// The program is busy and the user can't interact with it
+    Wait,
+    /// A table cell or set of cells can be selected
+    Cell,
+    /// A simple crosshair, for example for bitmap selection
+    Crosshair,
+    /// Text that may be selected
+    Text,
+    /// Vertical text that may be selected
+    VerticalText,
+    /// An alias or shortcut is to be created
+    Alias,
+    /// Something is to be copied
+    Copy,
+    /// Something is to be moved
+    Move,
+    /// An item may not be dropped at the current location
+    NoDrop,
+    /// The requested action will not be carried out
+    NotAllowed,
+    /// Something can be grabbed
+    Grab,
+    /// Something is being grabbed
+    Grabbing,
+    /// The east edge is to be moved
+    EResize,
+    /// The north edge is to be moved
+    NResize,
+    /// The north-east corner is to be moved
+    NeResize,
+    /// The north-west corner is to be moved
+    NwResize,
+    /// The south edge is to be moved
+    SResize,
+    /// The south-east corner is to be moved
+    SeResize,
+    /// The south-west corner is to be moved
+    SwResize,
+    /// The west edge is to be moved
+    WResize,
+    /// A bidirectional east-west resize
+    EwResize,
+    /// A bidirectional north-south resize
+    NsResize,
+    /// A bidirectional north-east/south-west resize
+    NeswResize,
+    /// A bidirectional north-west/south-east resize
+    NwseResize,
+    /// A column can be resized horizontally
+    ColResize,
+    /// A row can be resized vertically
+    RowResize,
+    /// Something can be scrolled in any direction
+    AllScroll,
+    /// Something can be zoomed in
+    ZoomIn,
+    /// Something can be zoomed out
+    ZoomOut,
+}
+
+const ALL_ICONS: &[CursorIcon] = &[
+    CursorIcon::Default,
+    CursorIcon::ContextMenu,
+    CursorIcon::Help,
+    CursorIcon::Pointer,
+    CursorIcon::Progress,
+    CursorIcon::Wait,
+    CursorIcon::Cell,
+    CursorIcon::Crosshair,
+    CursorIcon::Text,
+    CursorIcon::VerticalText,
+    CursorIcon::Alias,
+    CursorIcon::Copy,
+    CursorIcon::Move,
+    CursorIcon::NoDrop,
+    CursorIcon::NotAllowed,
+    CursorIcon::Grab,
+    CursorIcon::Grabbing,
+    CursorIcon::EResize,
+    CursorIcon::NResize,
+    CursorIcon::NeResize,
+    CursorIcon::NwResize,
+    CursorIcon::SResize,
+    CursorIcon::SeResize,
+    CursorIcon::SwResize,
+    CursorIcon::WResize,
+    CursorIcon::EwResize,
+    CursorIcon::NsResize,
+    CursorIcon::NeswResize,
+    CursorIcon::NwseResize,
+    CursorIcon::ColResize,
+    CursorIcon::RowResize,
+    CursorIcon::AllScroll,
+    CursorIcon::ZoomIn,
+    CursorIcon::ZoomOut,
+];
+
+impl CursorIcon {
+    /// Find the icon a cursor name refers to
+    ///
+    /// Both the CSS names and the legacy names are recognized. A CSS name always
+    /// refers to its own icon, and a legacy name shared by several icons refers to the
+    /// first of them in the order of this enum. Returns `None` if the name does not
+    /// match any known icon.
+    pub fn from_name(name: &str) -> Option<CursorIcon> {
+        ALL_ICONS
+            .iter()
+            .copied()
+            .find(|icon| icon.name() == name)
+            .or_else(|| ALL_ICONS.iter().copied().find(|icon| icon.names().contains(&name)))
+    }
+
+    /// The name of this icon in the CSS specification
+    pub fn name(&self) -> &'static str {
+        self.names()[0]
+    }
+
+    /// All the names this icon can be found under in cursor themes
+    ///
+    /// The CSS name comes first, followed by the legacy names used by older
+    /// themes, in order of preference. Some legacy names are shared by several
+    /// icons, see [`from_name`](#method.from_name) for how they are resolved.
+    pub fn names(&self) -> &'static [&'static str] {
+        match *self {
+            CursorIcon::Default => &["default", "left_ptr"],
+            CursorIcon::ContextMenu => &["context-menu"],
+            CursorIcon::Help => &["help", "question_arrow", "whats_this"],
+            CursorIcon::Pointer => &["pointer", "hand2", "hand1", "pointing_hand"],
+            CursorIcon::Progress => &["progress", "left_ptr_watch", "half-busy"],
+            CursorIcon::Wait => &["wait", "watch"],
+            CursorIcon::Cell => &["cell", "plus"],
+            CursorIcon::Crosshair => &["crosshair", "cross"],
+            CursorIcon::Text => &["text", "xterm", "ibeam"],
+            CursorIcon::VerticalText => &["vertical-text"],
+            CursorIcon::Alias => &["alias", "dnd-link"],
+            CursorIcon::Copy => &["copy", "dnd-copy"],
+            CursorIcon::Move => &["move", "dnd-move"],
+            CursorIcon::NoDrop => &["no-drop", "dnd-no-drop"],
+            CursorIcon::NotAllowed => &["not-allowed", "crossed_circle", "forbidden"],
+            CursorIcon::Grab => &["grab", "openhand", "hand1"],
+            CursorIcon::Grabbing => &["grabbing", "closedhand", "dnd-none"],
+            CursorIcon::EResize => &["e-resize", "right_side"],
+            CursorIcon::NResize => &["n-resize", "top_side"],
+            CursorIcon::NeResize => &["ne-resize", "top_right_corner"],
+            CursorIcon::NwResize => &["nw-resize", "top_left_corner"],
+            CursorIcon::SResize => &["s-resize", "bottom_side"],
+            CursorIcon::SeResize => &["se-resize", "bottom_right_corner"],
+            CursorIcon::SwResize => &["sw-resize", "bottom_left_corner"],
+            CursorIcon::WResize => &["w-resize", "left_side"],
+            CursorIcon::EwResize => &["ew-resize", "h_double_arrow", "sb_h_double_arrow"],
+            CursorIcon::NsResize => &["ns-resize", "v_double_arrow", "sb_v_double_arrow"],
+            CursorIcon::NeswResize => &["nesw-resize", "fd_double_arrow", "size_bdiag"],
+            CursorIcon::NwseResize => &["nwse-resize", "bd_double_arrow", "size_fdiag"],
+            CursorIcon::ColResize => &["col-resize", "sb_h_double_arrow", "split_h"],
+            CursorIcon::RowResize => &["row-resize", "sb_v_double_arrow", "split_v"],
+            CursorIcon::AllScroll => &["all-scroll", "fleur"],
+            CursorIcon::ZoomIn => &["zoom-in"],
+            CursorIcon::ZoomOut => &["zoom-out"],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn css_names_are_unique() {
+        for icon in ALL_ICONS {
+            assert_eq!(CursorIcon::from_name(icon.name()), Some(*icon));
+        }
+    }
+
+    #[test]
+    fn shared_names_resolve_by_precedence() {
+        for icon in ALL_ICONS {
+            for name in icon.names() {
+                let first = ALL_ICONS.iter().find(|i| i.names().contains(name)).unwrap();
+                assert_eq!(CursorIcon::from_name(name), Some(*first), "{}", name);
+            }
+        }
+        assert_eq!(CursorIcon::from_name("hand1"), Some(CursorIcon::Pointer));
+        assert_eq!(CursorIcon::from_name("sb_h_double_arrow"), Some(CursorIcon::EwResize));
+        assert_eq!(CursorIcon::from_name("sb_v_double_arrow"), Some(CursorIcon::NsResize));
+    }
+
+    #[test]
+    fn css_name_comes_first() {
+        assert_eq!(CursorIcon::Grab.name(), "grab");
+        assert!(CursorIcon::Grab.names().contains(&"hand1"));
+        assert!(CursorIcon::ColResize.names().contains(&"sb_h_double_arrow"));
+        assert_eq!(CursorIcon::from_name("unknown"), None);
+    }
+}
@@ -1,5 +1,7 @@
 //! Utilities to work with pointers and their icons
 
+mod cursor_icon;
 mod theme;
 
-pub use self::theme::{ThemeManager, ThemeSpec, ThemedPointer};
+pub use self::cursor_icon::CursorIcon;
+pub use self::theme::{CursorNotFound, ThemeManager, ThemeSpec, ThemedPointer};
@@ -10,6 +10,13 @@ use wayland_client::{
 };
 use wayland_cursor::{Cursor, CursorTheme};
 
+use super::CursorIcon;
+use crate::environment::{Environment, GlobalHandler};
+use crate::protocols::staging::cursor_shape::v1::client::{
+    wp_cursor_shape_device_v1::{Shape, WpCursorShapeDeviceV1},
+    wp_cursor_shape_manager_v1::WpCursorShapeManagerV1,
+};
+
 /// The specification of a cursor theme to be used by the ThemeManager
 #[derive(Debug)]
 pub enum ThemeSpec<'a> {
@@ -40,11 +47,16 @@ pub enum ThemeSpec<'a> {
 /// Is is also clone-able in case you need to handle several
 /// pointer theming from different places.
 ///
+/// If the compositor supports the cursor-shape protocol, the `ThemeManager` can
+/// use it to let the compositor draw the cursors itself, see
+/// [`init_with_cursor_shape_manager`](#method.init_with_cursor_shape_manager).
+///
 /// Note that it is however neither `Send` nor `Sync`
 #[derive(Debug, Clone)]
 pub struct ThemeManager {
     themes: Rc<RefCell<ScaledThemeList>>,
     compositor: Attached<wl_compositor::WlCompositor>,
+    cursor_shape_manager: Option<Attached<WpCursorShapeManagerV1>>,
 }
 
 impl ThemeManager {
@@ -56,7 +68,27 @@ impl ThemeManager {
         compositor: Attached<wl_compositor::WlCompositor>,
         shm: Attached<wl_shm::WlShm>,
     ) -> ThemeManager {
-        ThemeManager { compositor, themes: Rc::new(RefCell::new(ScaledThemeList::new(theme, shm))) }
+        ThemeManager::init_with_cursor_shape_manager(theme, compositor, shm, None)
+    }
+
+    /// Load a system pointer theme, preferring the cursor-shape protocol if available
+    ///
+    /// If `cursor_shape_manager` is provided, the cursors of the pointers themed by
+    /// this manager are drawn by the compositor whenever they can be expressed as a
+    /// [`CursorIcon`](enum.CursorIcon.html), with the user's exact theme and size. The
+    /// cursor theme is then only loaded if a cursor that is not part of the protocol
+    /// is requested.
+    pub fn init_with_cursor_shape_manager(
+        theme: ThemeSpec,
+        compositor: Attached<wl_compositor::WlCompositor>,
+        shm: Attached<wl_shm::WlShm>,
+        cursor_shape_manager: Option<Attached<WpCursorShapeManagerV1>>,
+    ) -> ThemeManager {
+        ThemeManager {
+            compositor,
+            themes: Rc::new(RefCell::new(ScaledThemeList::new(theme, shm))),
+            cursor_shape_manager,
+        }
     }
 
     /// Wrap a pointer to theme it
@@ -64,9 +96,13 @@ impl ThemeManager {
         let surface = self.compositor.create_surface();
         let inner = Rc::new(RefCell::new(PointerInner {
             surface: surface.detach(),
+            shape_device: self
+                .cursor_shape_manager
+                .as_ref()
+                .map(|mgr| mgr.get_pointer(&pointer).detach()),
             themes: self.themes.clone(),
             last_serial: 0,
-            current_cursor: "left_ptr".into(),
+            current_cursor: CurrentCursor::Icon(CursorIcon::Default),
             scale_factor: 1,
         }));
         let my_pointer = pointer.clone();
@@ -92,6 +128,9 @@ impl ThemeManager {
     /// You need to provide an implementation as if implementing a `wl_pointer`, but
     /// it will receive as `meta` argument a `ThemedPointer` wrapping your pointer,
     /// rather than a `WlPointer`.
+    ///
+    /// The pointer is automatically released when the seat loses its pointer capability
+    /// or is removed.
     pub fn theme_pointer_with_impl<F>(
         &self,
         seat: &Attached<wl_seat::WlSeat>,
@@ -100,17 +139,23 @@ impl ThemeManager {
     where
         F: FnMut(wl_pointer::Event, ThemedPointer, DispatchData) + 'static,
     {
+        let pointer = seat.get_pointer();
         let surface = self.compositor.create_surface();
         let inner = Rc::new(RefCell::new(PointerInner {
             surface: surface.detach(),
+            shape_device: self
+                .cursor_shape_manager
+                .as_ref()
+                .map(|mgr| mgr.get_pointer(&pointer).detach()),
             themes: self.themes.clone(),
             last_serial: 0,
-            current_cursor: "left_ptr".into(),
+            current_cursor: CurrentCursor::Icon(CursorIcon::Default),
             scale_factor: 1,
         }));
 
+        crate::seat::track_device(seat, crate::seat::SeatDevice::Pointer(pointer.detach()));
+
         let inner2 = inner.clone();
-        let pointer = seat.get_pointer();
         pointer.quick_assign(move |ptr, event, ddata| {
             callback(event, ThemedPointer { pointer: ptr.detach(), inner: inner2.clone() }, ddata)
         });
@@ -156,15 +201,27 @@ impl ScaledThemeList {
         ScaledThemeList { shm, name, size, themes: vec![] }
     }
 
-    fn get_cursor(&mut self, name: &str, scale: u32) -> Option<&Cursor> {
+    fn get_theme(&mut self, scale: u32) -> &mut CursorTheme {
         // Check if we already loaded the theme for this scale factor
         let opt_index = self.themes.iter().position(|&(s, _)| s == scale);
         if let Some(idx) = opt_index {
-            self.themes[idx].1.get_cursor(name)
+            &mut self.themes[idx].1
         } else {
             let new_theme = CursorTheme::load_from_name(&self.name, self.size * scale, &self.shm);
             self.themes.push((scale, new_theme));
-            self.themes.last_mut().unwrap().1.get_cursor(name)
+            &mut self.themes.last_mut().unwrap().1
+        }
+    }
+
+    fn get_cursor(&mut self, cursor: &CurrentCursor, scale: u32) -> Option<&Cursor> {
+        let theme = self.get_theme(scale);
+        match *cursor {
+            CurrentCursor::Named(ref name) => theme.get_cursor(name),
+            CurrentCursor::Icon(icon) => {
+                // Use the first name of the icon that the theme knows about
+                let name = icon.names().iter().find(|name| theme.get_cursor(name).is_some())?;
+                theme.get_cursor(name)
+            }
         }
     }
 }
@@ -181,17 +238,39 @@ impl fmt::Debug for ScaledThemeList {
     }
 }
 
+#[derive(Debug, Clone)]
+enum CurrentCursor {
+    Named(String),
+    Icon(CursorIcon),
+}
+
+impl CurrentCursor {
+    fn icon(&self) -> Option<CursorIcon> {
+        match *self {
+            CurrentCursor::Named(ref name) => CursorIcon::from_name(name),
+            CurrentCursor::Icon(icon) => Some(icon),
+        }
+    }
+}
+
 #[derive(Debug)]
 struct PointerInner {
     surface: wl_surface::WlSurface,
+    shape_device: Option<WpCursorShapeDeviceV1>,
     themes: Rc<RefCell<ScaledThemeList>>,
-    current_cursor: String,
+    current_cursor: CurrentCursor,
     last_serial: u32,
     scale_factor: i32,
 }
 
 impl PointerInner {
     fn update_cursor(&self, pointer: &wl_pointer::WlPointer) -> Result<(), CursorNotFound> {
+        // Let the compositor draw the cursor if it knows about it
+        if let (Some(device), Some(icon)) = (&self.shape_device, self.current_cursor.icon()) {
+            device.set_shape(self.last_serial, cursor_shape(icon));
+            return Ok(());
+        }
+
         let mut themes = self.themes.borrow_mut();
         let scale = self.scale_factor as u32;
         let cursor = themes.get_cursor(&self.current_cursor, scale).ok_or(CursorNotFound)?;
@@ -237,6 +316,10 @@ impl ThemedPointer {
     /// Possible names depend on the theme. Does nothing and returns
     /// `Err` if given name is not available.
     ///
+    /// If the compositor draws the cursors using the cursor-shape protocol, names
+    /// recognized by [`CursorIcon::from_name`](enum.CursorIcon.html#method.from_name)
+    /// are forwarded to it rather than looked up in the theme.
+    ///
     /// If this is done as an answer to an input event, you need to provide
     /// the associated serial otherwise the server may ignore the request.
     pub fn set_cursor(&self, name: &str, serial: Option<u32>) -> Result<(), CursorNotFound> {
@@ -244,7 +327,28 @@ impl ThemedPointer {
         if let Some(s) = serial {
             inner.last_serial = s;
         }
-        inner.current_cursor = name.into();
+        inner.current_cursor = CurrentCursor::Named(name.into());
+        inner.update_cursor(&self.pointer)
+    }
+
+    /// Change the cursor to the given cursor icon
+    ///
+    /// The names associated to this icon are tried in order until one of them
+    /// is found in the theme. Does nothing and returns `Err` if none of them is
+    /// available.
+    ///
+    /// If this is done as an answer to an input event, you need to provide
+    /// the associated serial otherwise the server may ignore the request.
+    pub fn set_cursor_icon(
+        &self,
+        icon: CursorIcon,
+        serial: Option<u32>,
+    ) -> Result<(), CursorNotFound> {
+        let mut inner = self.inner.borrow_mut();
+        if let Some(s) = serial {
+            inner.last_serial = s;
+        }
+        inner.current_cursor = CurrentCursor::Icon(icon);
         inner.update_cursor(&self.pointer)
     }
 }
@@ -258,10 +362,73 @@ impl Deref for ThemedPointer {
 
 impl Drop for PointerInner {
     fn drop(&mut self) {
+        if let Some(ref device) = self.shape_device {
+            device.destroy();
+        }
         self.surface.destroy();
     }
 }
 
+fn cursor_shape(icon: CursorIcon) -> Shape {
+    match icon {
+        CursorIcon::Default => Shape::Default,
+        CursorIcon::ContextMenu => Shape::ContextMenu,
+        CursorIcon::Help => Shape::Help,
+        CursorIcon::Pointer => Shape::Pointer,
+        CursorIcon::Progress => Shape::Progress,
+        CursorIcon::Wait => Shape::Wait,
+        CursorIcon::Cell => Shape::Cell,
+        CursorIcon::Crosshair => Shape::Crosshair,
+        CursorIcon::Text => Shape::Text,
+        CursorIcon::VerticalText => Shape::VerticalText,
+        CursorIcon::Alias => Shape::Alias,
+        CursorIcon::Copy => Shape::Copy,
+        CursorIcon::Move => Shape::Move,
+        CursorIcon::NoDrop => Shape::NoDrop,
+        CursorIcon::NotAllowed => Shape::NotAllowed,
+        CursorIcon::Grab => Shape::Grab,
+        CursorIcon::Grabbing => Shape::Grabbing,
+        CursorIcon::EResize => Shape::EResize,
+        CursorIcon::NResize => Shape::NResize,
+        CursorIcon::NeResize => Shape::NeResize,
+        CursorIcon::NwResize => Shape::NwResize,
+        CursorIcon::SResize => Shape::SResize,
+        CursorIcon::SeResize => Shape::SeResize,
+        CursorIcon::SwResize => Shape::SwResize,
+        CursorIcon::WResize => Shape::WResize,
+        CursorIcon::EwResize => Shape::EwResize,
+        CursorIcon::NsResize => Shape::NsResize,
+        CursorIcon::NeswResize => Shape::NeswResize,
+        CursorIcon::NwseResize => Shape::NwseResize,
+        CursorIcon::ColResize => Shape::ColResize,
+        CursorIcon::RowResize => Shape::RowResize,
+        CursorIcon::AllScroll => Shape::AllScroll,
+        CursorIcon::ZoomIn => Shape::ZoomIn,
+        CursorIcon::ZoomOut => Shape::ZoomOut,
+    }
+}
+
+impl<E> Environment<E>
+where
+    E: GlobalHandler<wl_compositor::WlCompositor>
+        + GlobalHandler<wl_shm::WlShm>
+        + GlobalHandler<WpCursorShapeManagerV1>,
+{
+    /// Create a `ThemeManager` for given theme
+    ///
+    /// The cursor-shape global is used if the compositor advertised it, otherwise the
+    /// cursor images are loaded from the theme, see
+    /// [`ThemeManager::init_with_cursor_shape_manager`](../seat/pointer/struct.ThemeManager.html#method.init_with_cursor_shape_manager).
+    pub fn create_theme_manager(&self, theme: ThemeSpec) -> ThemeManager {
+        ThemeManager::init_with_cursor_shape_manager(
+            theme,
+            self.require_global(),
+            self.require_global(),
+            self.get_global(),
+        )
+    }
+}
+
 /// An error representing the fact that the required cursor was not found
 #[derive(Debug, Copy, Clone)]
 pub struct CursorNotFound;
@@ -0,0 +1,315 @@
+use std::{
+    cell::RefCell,
+    fmt,
+    rc::{Rc, Weak},
+};
+
+use log::error;
+use wayland_client::{
+    protocol::{wl_compositor, wl_keyboard, wl_pointer, wl_seat, wl_shm, wl_surface, wl_touch},
+    Attached, DispatchData,
+};
+
+use super::{
+    keyboard::{self, map_keyboard},
+    pointer::{ThemeManager, ThemeSpec, ThemedPointer},
+    SeatData, SeatDevice, SeatHandling, SeatListener,
+};
+use crate::environment::{Environment, GlobalHandler};
+use crate::protocols::staging::cursor_shape::v1::client::wp_cursor_shape_manager_v1::WpCursorShapeManagerV1;
+
+/// Events generated by the devices of a seat managed by a [`SeatState`](struct.SeatState.html)
+#[derive(Debug)]
+pub enum SeatEvent<'a> {
+    /// An event from the keyboard of the seat, interpreted with its keymap
+    Keyboard {
+        /// The keyboard that generated the event
+        keyboard: wl_keyboard::WlKeyboard,
+        /// The event
+        event: keyboard::Event<'a>,
+    },
+    /// An event from the pointer of the seat
+    Pointer {
+        /// The pointer that generated the event, which you can use to change the cursor
+        pointer: ThemedPointer,
+        /// The event
+        event: wl_pointer::Event,
+    },
+    /// An event from the touchscreen of the seat
+    Touch {
+        /// The touch device that generated the event
+        touch: wl_touch::WlTouch,
+        /// The event
+        event: wl_touch::Event,
+    },
+}
+
+type SeatEventCallback = dyn FnMut(SeatEvent<'_>, DispatchData);
+
+/// Aggregated input state of a seat
+///
+/// This object follows the capabilities of a seat: it creates its keyboard, pointer
+/// and touch devices when they become available and releases them when they are
+/// removed from the seat, or when the seat itself is removed. All the events of these
+/// devices are routed to a single callback as [`SeatEvent`](enum.SeatEvent.html)s.
+///
+/// It also tracks which surfaces currently have the focus of each of its devices.
+///
+/// The keyboard is handled with [`map_keyboard`](keyboard/fn.map_keyboard.html) using the
+/// keymap provided by the compositor, so key repetition is not handled, and the pointer
+/// is themed using a [`ThemeManager`](pointer/struct.ThemeManager.html).
+///
+/// Its devices are released by the [`SeatHandler`](struct.SeatHandler.html) when the
+/// associated capability is withdrawn, and dropping the `SeatState` releases them as well. It is created with
+/// [`Environment::create_seat_state`](../environment/struct.Environment.html#method.create_seat_state).
+pub struct SeatState {
+    inner: Rc<RefCell<SeatStateInner>>,
+    _listener: SeatListener,
+}
+
+struct SeatStateInner {
+    seat: Attached<wl_seat::WlSeat>,
+    theme_manager: ThemeManager,
+    callback: Rc<RefCell<SeatEventCallback>>,
+    keyboard: Option<wl_keyboard::WlKeyboard>,
+    pointer: Option<ThemedPointer>,
+    touch: Option<wl_touch::WlTouch>,
+    keyboard_focus: Option<wl_surface::WlSurface>,
+    pointer_focus: Option<wl_surface::WlSurface>,
+    touch_points: Vec<(i32, wl_surface::WlSurface)>,
+}
+
+impl SeatState {
+    fn new(
+        seat: &Attached<wl_seat::WlSeat>,
+        theme_manager: ThemeManager,
+        callback: Rc<RefCell<SeatEventCallback>>,
+        listener: impl FnOnce(Weak<RefCell<SeatStateInner>>) -> SeatListener,
+    ) -> SeatState {
+        let inner = Rc::new(RefCell::new(SeatStateInner {
+            seat: seat.clone(),
+            theme_manager,
+            callback,
+            keyboard: None,
+            pointer: None,
+            touch: None,
+            keyboard_focus: None,
+            pointer_focus: None,
+            touch_points: Vec::new(),
+        }));
+
+        if let Some(data) = super::clone_seat_data(seat) {
+            SeatStateInner::update(&inner, &data);
+        }
+
+        let listener = listener(Rc::downgrade(&inner));
+
+        SeatState { inner, _listener: listener }
+    }
+
+    /// The seat managed by this `SeatState`
+    pub fn seat(&self) -> Attached<wl_seat::WlSeat> {
+        self.inner.borrow().seat.clone()
+    }
+
+    /// The keyboard of this seat, if it currently has one
+    pub fn keyboard(&self) -> Option<wl_keyboard::WlKeyboard> {
+        self.inner.borrow().keyboard.clone()
+    }
+
+    /// The pointer of this seat, if it currently has one
+    pub fn pointer(&self) -> Option<ThemedPointer> {
+        self.inner.borrow().pointer.clone()
+    }
+
+    /// The touch device of this seat, if it currently has one
+    pub fn touch(&self) -> Option<wl_touch::WlTouch> {
+        self.inner.borrow().touch.clone()
+    }
+
+    /// The surface that currently has the keyboard focus
+    pub fn keyboard_focus(&self) -> Option<wl_surface::WlSurface> {
+        self.inner.borrow().keyboard_focus.clone()
+    }
+
+    /// The surface the pointer is currently over
+    pub fn pointer_focus(&self) -> Option<wl_surface::WlSurface> {
+        self.inner.borrow().pointer_focus.clone()
+    }
+
+    /// The surface the touch point with given id is currently on
+    pub fn touch_focus(&self, id: i32) -> Option<wl_surface::WlSurface> {
+        self.inner.borrow().touch_points.iter().find(|&&(i, _)| i == id).map(|(_, s)| s.clone())
+    }
+}
+
+impl SeatStateInner {
+    fn update(me: &Rc<RefCell<SeatStateInner>>, data: &SeatData) {
+        let mut inner = me.borrow_mut();
+
+        if data.has_keyboard && !data.defunct {
+            if inner.keyboard.is_none() {
+                let weak = Rc::downgrade(me);
+                let callback = inner.callback.clone();
+                match map_keyboard(&inner.seat, None, move |event, keyboard, ddata| {
+                    if let Some(inner) = Weak::upgrade(&weak) {
+                        let mut inner = inner.borrow_mut();
+                        match event {
+                            keyboard::Event::Enter { ref surface, .. } => {
+                                inner.keyboard_focus = Some(surface.clone())
+                            }
+                            keyboard::Event::Leave { .. } => inner.keyboard_focus = None,
+                            _ => {}
+                        }
+                    }
+                    (*callback.borrow_mut())(SeatEvent::Keyboard { keyboard, event }, ddata);
+                }) {
+                    Ok(keyboard) => inner.keyboard = Some(keyboard),
+                    Err(e) => error!("Failed to map keyboard on seat {}: {:?}", data.name, e),
+                }
+            }
+        } else if inner.keyboard.take().is_some() {
+            // the keyboard is released by the `SeatHandler`
+            inner.keyboard_focus = None;
+        }
+
+        if data.has_pointer && !data.defunct {
+            if inner.pointer.is_none() {
+                let weak = Rc::downgrade(me);
+                let callback = inner.callback.clone();
+                let pointer = inner.theme_manager.theme_pointer_with_impl(
+                    &inner.seat,
+                    move |event, pointer, ddata| {
+                        if let Some(inner) = Weak::upgrade(&weak) {
+                            let mut inner = inner.borrow_mut();
+                            match event {
+                                wl_pointer::Event::Enter { ref surface, .. } => {
+                                    inner.pointer_focus = Some(surface.clone())
+                                }
+                                wl_pointer::Event::Leave { .. } => inner.pointer_focus = None,
+                                _ => {}
+                            }
+                        }
+                        (*callback.borrow_mut())(SeatEvent::Pointer { pointer, event }, ddata);
+                    },
+                );
+                inner.pointer = Some(pointer);
+            }
+        } else if inner.pointer.take().is_some() {
+            // the pointer is released by the `SeatHandler`
+            inner.pointer_focus = None;
+        }
+
+        if data.has_touch && !data.defunct {
+            if inner.touch.is_none() {
+                let weak = Rc::downgrade(me);
+                let callback = inner.callback.clone();
+                let touch = inner.seat.get_touch();
+                touch.quick_assign(move |touch, event, ddata| {
+                    if let Some(inner) = Weak::upgrade(&weak) {
+                        let mut inner = inner.borrow_mut();
+                        match event {
+                            wl_touch::Event::Down { ref surface, id, .. } => {
+                                inner.touch_points.retain(|&(i, _)| i != id);
+                                inner.touch_points.push((id, surface.clone()));
+                            }
+                            wl_touch::Event::Up { id, .. } => {
+                                inner.touch_points.retain(|&(i, _)| i != id)
+                            }
+                            wl_touch::Event::Cancel => inner.touch_points.clear(),
+                            _ => {}
+                        }
+                    }
+                    (*callback.borrow_mut())(
+                        SeatEvent::Touch { touch: touch.detach(), event },
+                        ddata,
+                    );
+                });
+                super::track_device(&inner.seat, SeatDevice::Touch(touch.detach()));
+                inner.touch = Some(touch.detach());
+            }
+        } else if inner.touch.take().is_some() {
+            // the touch is released by the `SeatHandler`
+            inner.touch_points.clear();
+        }
+    }
+}
+
+impl Drop for SeatStateInner {
+    fn drop(&mut self) {
+        if let Some(keyboard) = self.keyboard.take() {
+            SeatDevice::Keyboard(keyboard).release();
+        }
+        if let Some(pointer) = self.pointer.take() {
+            SeatDevice::Pointer((*pointer).clone()).release();
+        }
+        if let Some(touch) = self.touch.take() {
+            SeatDevice::Touch(touch).release();
+        }
+    }
+}
+
+impl fmt::Debug for SeatState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SeatState").field("inner", &self.inner).finish()
+    }
+}
+
+impl fmt::Debug for SeatStateInner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SeatStateInner")
+            .field("seat", &self.seat)
+            .field("theme_manager", &self.theme_manager)
+            .field("callback", &"Fn(..) -> { ... }")
+            .field("keyboard", &self.keyboard)
+            .field("pointer", &self.pointer)
+            .field("touch", &self.touch)
+            .field("keyboard_focus", &self.keyboard_focus)
+            .field("pointer_focus", &self.pointer_focus)
+            .field("touch_points", &self.touch_points)
+            .finish()
+    }
+}
+
+impl<E> Environment<E>
+where
+    E: SeatHandling
+        + GlobalHandler<wl_compositor::WlCompositor>
+        + GlobalHandler<wl_shm::WlShm>
+        + GlobalHandler<WpCursorShapeManagerV1>,
+{
+    /// Manage the input devices of a seat
+    ///
+    /// The returned [`SeatState`](../seat/struct.SeatState.html) creates and releases the
+    /// keyboard, pointer and touch devices of this seat as its capabilities change, and
+    /// forwards all their events to the provided callback.
+    ///
+    /// The pointer is themed using the provided `ThemeManager`, or if `None` is given, using
+    /// the cursor-shape protocol when the compositor supports it and the system cursor theme
+    /// otherwise, see [`create_theme_manager`](#method.create_theme_manager).
+    pub fn create_seat_state<F>(
+        &self,
+        seat: &Attached<wl_seat::WlSeat>,
+        theme_manager: Option<ThemeManager>,
+        callback: F,
+    ) -> SeatState
+    where
+        F: FnMut(SeatEvent<'_>, DispatchData) + 'static,
+    {
+        let theme_manager =
+            theme_manager.unwrap_or_else(|| self.create_theme_manager(ThemeSpec::System));
+        let callback = Rc::new(RefCell::new(callback)) as Rc<RefCell<SeatEventCallback>>;
+        let my_seat = seat.detach();
+
+        SeatState::new(seat, theme_manager, callback, |weak| {
+            self.listen_for_seats(move |seat, data, _| {
+                if *seat != my_seat {
+                    return;
+                }
+                if let Some(inner) = Weak::upgrade(&weak) {
+                    SeatStateInner::update(&inner, data);
+                }
+            })
+        })
+    }
+}
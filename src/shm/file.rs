@@ -0,0 +1,269 @@
+use std::{
+    collections::hash_map::RandomState,
+    env, fmt,
+    fs::{self, File, OpenOptions},
+    hash::{BuildHasher, Hasher},
+    io,
+    os::unix::{fs::OpenOptionsExt, io::FromRawFd},
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+#[cfg(target_os = "linux")]
+use nix::sys::memfd;
+#[cfg(target_os = "linux")]
+use std::ffi::CStr;
+
+use nix::{
+    errno::Errno,
+    fcntl,
+    sys::{mman, stat},
+    unistd,
+};
+
+// number of names tried before giving up when creating a named file
+const NAME_ATTEMPTS: usize = 64;
+
+/// How the shared memory files backing the memory pools are created
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ShmFileStrategy {
+    /// Try `memfd_create`, then `shm_open`, then a file in `XDG_RUNTIME_DIR`
+    ///
+    /// This is the default.
+    Auto,
+    /// Only use `memfd_create`, which is only available on Linux
+    Memfd,
+    /// Only use `shm_open`
+    ShmOpen,
+    /// Only use a file in `XDG_RUNTIME_DIR`, which is unlinked right after its creation
+    RuntimeDir,
+}
+
+impl ShmFileStrategy {
+    fn from_usize(v: usize) -> ShmFileStrategy {
+        match v {
+            1 => ShmFileStrategy::Memfd,
+            2 => ShmFileStrategy::ShmOpen,
+            3 => ShmFileStrategy::RuntimeDir,
+            _ => ShmFileStrategy::Auto,
+        }
+    }
+
+    fn to_usize(self) -> usize {
+        match self {
+            ShmFileStrategy::Auto => 0,
+            ShmFileStrategy::Memfd => 1,
+            ShmFileStrategy::ShmOpen => 2,
+            ShmFileStrategy::RuntimeDir => 3,
+        }
+    }
+}
+
+static STRATEGY: AtomicUsize = AtomicUsize::new(0);
+
+/// Set the strategy used to create the shared memory files of the memory pools
+///
+/// This applies to the whole process, and to all the files created after this call.
+pub fn set_shm_file_strategy(strategy: ShmFileStrategy) {
+    STRATEGY.store(strategy.to_usize(), Ordering::Relaxed);
+}
+
+/// The strategy currently used to create the shared memory files of the memory pools
+pub fn shm_file_strategy() -> ShmFileStrategy {
+    ShmFileStrategy::from_usize(STRATEGY.load(Ordering::Relaxed))
+}
+
+/// An error that occurred while creating a shared memory file
+///
+/// The memory pools return it wrapped in an `io::Error`, from which it can be retrieved
+/// using `get_ref` and `downcast_ref`.
+#[derive(Debug)]
+pub enum ShmFileError {
+    /// `memfd_create` is not available on this system
+    MemfdUnsupported,
+    /// `memfd_create` failed
+    Memfd(io::Error),
+    /// `shm_open` failed
+    ShmOpen(io::Error),
+    /// `XDG_RUNTIME_DIR` is not set
+    NoRuntimeDir,
+    /// Creating a file in `XDG_RUNTIME_DIR` failed
+    RuntimeDir {
+        /// The path of the file that could not be created
+        path: PathBuf,
+        /// The error that occurred
+        error: io::Error,
+    },
+    /// Every strategy failed, the errors are given in the order the strategies were tried
+    AllFailed(Vec<ShmFileError>),
+}
+
+impl ShmFileError {
+    fn kind(&self) -> io::ErrorKind {
+        match *self {
+            ShmFileError::MemfdUnsupported | ShmFileError::NoRuntimeDir => io::ErrorKind::NotFound,
+            ShmFileError::Memfd(ref error)
+            | ShmFileError::ShmOpen(ref error)
+            | ShmFileError::RuntimeDir { ref error, .. } => error.kind(),
+            ShmFileError::AllFailed(ref errors) => {
+                errors.last().map(ShmFileError::kind).unwrap_or(io::ErrorKind::Other)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ShmFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            ShmFileError::Memfd(ref error)
+            | ShmFileError::ShmOpen(ref error)
+            | ShmFileError::RuntimeDir { ref error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ShmFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ShmFileError::MemfdUnsupported => f.write_str("memfd_create is not supported"),
+            ShmFileError::Memfd(ref e) => write!(f, "memfd_create failed: {}", e),
+            ShmFileError::ShmOpen(ref e) => write!(f, "shm_open failed: {}", e),
+            ShmFileError::NoRuntimeDir => f.write_str("XDG_RUNTIME_DIR is not set"),
+            ShmFileError::RuntimeDir { ref path, ref error } => {
+                write!(f, "failed to create {}: {}", path.display(), error)
+            }
+            ShmFileError::AllFailed(ref errors) => {
+                f.write_str("failed to create a shared memory file")?;
+                for (i, e) in errors.iter().enumerate() {
+                    write!(f, "{} {}", if i == 0 { ":" } else { ";" }, e)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl From<ShmFileError> for io::Error {
+    fn from(e: ShmFileError) -> io::Error {
+        io::Error::new(e.kind(), e)
+    }
+}
+
+/// Create a shared memory file with given strategy
+///
+/// The file is anonymous: it has no name in the filesystem, and can only be shared by
+/// passing its file descriptor.
+pub fn create_shm_file(strategy: ShmFileStrategy) -> Result<File, ShmFileError> {
+    match strategy {
+        ShmFileStrategy::Memfd => create_memfd(),
+        ShmFileStrategy::ShmOpen => create_shm_open(),
+        ShmFileStrategy::RuntimeDir => create_in_runtime_dir(),
+        ShmFileStrategy::Auto => {
+            let mut errors = Vec::new();
+            let strategies: [fn() -> Result<File, ShmFileError>; 3] =
+                [create_memfd, create_shm_open, create_in_runtime_dir];
+            for create in strategies.iter() {
+                match create() {
+                    Ok(file) => return Ok(file),
+                    Err(ShmFileError::MemfdUnsupported) => {}
+                    Err(e) => {
+                        log::debug!("Failed to create a shared memory file: {}", e);
+                        errors.push(e);
+                    }
+                }
+            }
+            Err(ShmFileError::AllFailed(errors))
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn create_memfd() -> Result<File, ShmFileError> {
+    loop {
+        match memfd::memfd_create(
+            CStr::from_bytes_with_nul(b"smithay-client-toolkit\0").unwrap(),
+            memfd::MemFdCreateFlag::MFD_CLOEXEC | memfd::MemFdCreateFlag::MFD_ALLOW_SEALING,
+        ) {
+            Ok(fd) => {
+                // this is only an optimization, so ignore errors
+                let _ = fcntl::fcntl(
+                    fd,
+                    fcntl::F_ADD_SEALS(
+                        fcntl::SealFlag::F_SEAL_SHRINK | fcntl::SealFlag::F_SEAL_SEAL,
+                    ),
+                );
+                return Ok(unsafe { File::from_raw_fd(fd) });
+            }
+            Err(Errno::EINTR) => continue,
+            Err(Errno::ENOSYS) => return Err(ShmFileError::MemfdUnsupported),
+            Err(errno) => return Err(ShmFileError::Memfd(errno.into())),
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn create_memfd() -> Result<File, ShmFileError> {
+    Err(ShmFileError::MemfdUnsupported)
+}
+
+fn create_shm_open() -> Result<File, ShmFileError> {
+    let mut attempts = 0;
+    loop {
+        let name = format!("/smithay-client-toolkit-{:016x}", random_u64());
+        match mman::shm_open(
+            name.as_str(),
+            fcntl::OFlag::O_CREAT
+                | fcntl::OFlag::O_EXCL
+                | fcntl::OFlag::O_RDWR
+                | fcntl::OFlag::O_CLOEXEC,
+            stat::Mode::S_IRUSR | stat::Mode::S_IWUSR,
+        ) {
+            Ok(fd) => {
+                return match mman::shm_unlink(name.as_str()) {
+                    Ok(()) => Ok(unsafe { File::from_raw_fd(fd) }),
+                    Err(errno) => {
+                        let _ = unistd::close(fd);
+                        Err(ShmFileError::ShmOpen(errno.into()))
+                    }
+                };
+            }
+            // another process used the same name, try another one
+            Err(Errno::EEXIST) if attempts < NAME_ATTEMPTS => attempts += 1,
+            Err(Errno::EINTR) => continue,
+            Err(errno) => return Err(ShmFileError::ShmOpen(errno.into())),
+        }
+    }
+}
+
+fn create_in_runtime_dir() -> Result<File, ShmFileError> {
+    let dir = env::var_os("XDG_RUNTIME_DIR").ok_or(ShmFileError::NoRuntimeDir)?;
+    let mut attempts = 0;
+    loop {
+        let path =
+            PathBuf::from(&dir).join(format!("smithay-client-toolkit-{:016x}", random_u64()));
+        match OpenOptions::new().read(true).write(true).create_new(true).mode(0o600).open(&path) {
+            Ok(file) => {
+                return match fs::remove_file(&path) {
+                    Ok(()) => Ok(file),
+                    Err(error) => Err(ShmFileError::RuntimeDir { path, error }),
+                };
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists && attempts < NAME_ATTEMPTS => {
+                attempts += 1
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(ShmFileError::RuntimeDir { path, error }),
+        }
+    }
+}
+
+// A random number, used to name the files
+fn random_u64() -> u64 {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    // the keys of a new RandomState are derived from the random number generator of the OS
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_usize(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.write_u32(std::process::id());
+    hasher.finish()
+}
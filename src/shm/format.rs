@@ -0,0 +1,381 @@
+use std::{convert::TryFrom, fmt, io};
+
+use wayland_client::{protocol::wl_shm, Attached};
+
+/// The list of formats advertised by the compositor, stored in the user data of the `wl_shm`
+/// global by the `ShmHandler`
+pub(crate) type ShmFormats = std::rc::Rc<std::cell::RefCell<Vec<wl_shm::Format>>>;
+
+/// Number of bytes used by a pixel of given format
+///
+/// Returns `None` for formats that are not supported by the typed helpers of this module:
+/// `Argb8888`, `Xrgb8888`, `Abgr8888`, `Xbgr8888`, `Rgb565`, `Abgr2101010`, `Xbgr2101010`,
+/// `Abgr16161616f` and `Xbgr16161616f`.
+pub fn bytes_per_pixel(format: wl_shm::Format) -> Option<u32> {
+    use wl_shm::Format::*;
+    match format {
+        Rgb565 => Some(2),
+        Argb8888 | Xrgb8888 | Abgr8888 | Xbgr8888 | Abgr2101010 | Xbgr2101010 => Some(4),
+        Abgr16161616f | Xbgr16161616f => Some(8),
+        _ => None,
+    }
+}
+
+/// The smallest stride of a buffer of given format and width, in bytes
+///
+/// Returns `None` if the format is not supported, see [`bytes_per_pixel`](fn.bytes_per_pixel.html),
+/// or if the stride does not fit in an `i32`.
+pub fn stride(format: wl_shm::Format, width: u32) -> Option<i32> {
+    bytes_per_pixel(format)
+        .and_then(|bpp| bpp.checked_mul(width))
+        .and_then(|stride| i32::try_from(stride).ok())
+}
+
+/// Check whether the compositor advertised given format
+///
+/// `Argb8888` and `Xrgb8888` are always supported. For other formats, this uses the list
+/// collected by the [`ShmHandler`](struct.ShmHandler.html) that bound this `wl_shm`; if the
+/// global was bound by something else, the format is assumed to be supported.
+pub fn is_format_supported(shm: &Attached<wl_shm::WlShm>, format: wl_shm::Format) -> bool {
+    match shm.as_ref().user_data().get::<ShmFormats>() {
+        Some(formats) => format_in_list(Some(&formats.borrow()), format),
+        None => true,
+    }
+}
+
+// Whether a format is in the list advertised by the compositor, `None` if the list is unknown
+fn format_in_list(formats: Option<&[wl_shm::Format]>, format: wl_shm::Format) -> bool {
+    if format == wl_shm::Format::Argb8888 || format == wl_shm::Format::Xrgb8888 {
+        return true;
+    }
+    formats.map(|formats| formats.contains(&format)).unwrap_or(true)
+}
+
+/// An error that occurred while creating a typed buffer
+#[derive(Debug)]
+pub enum CanvasError {
+    /// The format was not advertised by the compositor
+    UnsupportedFormat(wl_shm::Format),
+    /// The format is not supported by the typed helpers
+    UnknownFormat(wl_shm::Format),
+    /// The buffer would have a zero width or height, or is too large
+    InvalidSize {
+        /// The requested width
+        width: u32,
+        /// The requested height
+        height: u32,
+    },
+    /// The buffer would start at a negative offset in the pool
+    InvalidOffset(i32),
+    /// The pool failed to allocate memory for the buffer
+    Io(io::Error),
+}
+
+impl std::error::Error for CanvasError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            CanvasError::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CanvasError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            CanvasError::UnsupportedFormat(format) => {
+                write!(f, "format {:?} is not supported by the compositor", format)
+            }
+            CanvasError::UnknownFormat(format) => write!(f, "unknown pixel format {:?}", format),
+            CanvasError::InvalidSize { width, height } => {
+                write!(f, "invalid buffer size {}x{}", width, height)
+            }
+            CanvasError::InvalidOffset(offset) => write!(f, "invalid buffer offset {}", offset),
+            CanvasError::Io(ref e) => write!(f, "failed to allocate buffer: {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for CanvasError {
+    fn from(e: io::Error) -> CanvasError {
+        CanvasError::Io(e)
+    }
+}
+
+// Validate the size and format of a typed buffer and compute its stride
+pub(crate) fn check_format(
+    shm: &Attached<wl_shm::WlShm>,
+    format: wl_shm::Format,
+    width: u32,
+    height: u32,
+) -> Result<i32, CanvasError> {
+    match shm.as_ref().user_data().get::<ShmFormats>() {
+        Some(formats) => check_buffer(Some(&formats.borrow()), format, width, height),
+        None => check_buffer(None, format, width, height),
+    }
+}
+
+// Same as `check_format`, against a list of formats advertised by the compositor
+pub(crate) fn check_buffer(
+    formats: Option<&[wl_shm::Format]>,
+    format: wl_shm::Format,
+    width: u32,
+    height: u32,
+) -> Result<i32, CanvasError> {
+    let bpp = bytes_per_pixel(format).ok_or(CanvasError::UnknownFormat(format))?;
+    // `wl_shm_pool.create_buffer` raises a protocol error for empty buffers, and takes
+    // its sizes as `i32`s
+    let stride = bpp.checked_mul(width).and_then(|stride| i32::try_from(stride).ok());
+    let stride = match stride {
+        Some(stride) if width > 0 && height > 0 && i32::try_from(height).is_ok() => stride,
+        _ => return Err(CanvasError::InvalidSize { width, height }),
+    };
+    if !format_in_list(formats, format) {
+        return Err(CanvasError::UnsupportedFormat(format));
+    }
+    Ok(stride)
+}
+
+/// A color, with components in the `[0, 1]` range
+///
+/// As is usual with Wayland, the color components are expected to be premultiplied
+/// by the alpha component.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Color {
+    /// The red component
+    pub r: f32,
+    /// The green component
+    pub g: f32,
+    /// The blue component
+    pub b: f32,
+    /// The alpha component
+    pub a: f32,
+}
+
+impl Color {
+    /// Create a color from its components
+    pub fn rgba(r: f32, g: f32, b: f32, a: f32) -> Color {
+        Color { r, g, b, a }
+    }
+
+    /// Create a color from a `0xAARRGGBB` value
+    pub fn from_argb8888(argb: u32) -> Color {
+        let c = |shift: u32| ((argb >> shift) & 0xFF) as f32 / 255.0;
+        Color { r: c(16), g: c(8), b: c(0), a: c(24) }
+    }
+
+    /// Encode this color as a pixel of given format
+    ///
+    /// The pixel value is returned in the lower bits of a `u64`, following the bit
+    /// layout of the format. Returns `None` if the format is not supported, see
+    /// [`bytes_per_pixel`](fn.bytes_per_pixel.html).
+    pub fn encode(&self, format: wl_shm::Format) -> Option<u64> {
+        use wl_shm::Format::*;
+        let unorm = |v: f32, bits: u32| {
+            let max = (1u64 << bits) - 1;
+            if v <= 0.0 {
+                0
+            } else if v >= 1.0 {
+                max
+            } else {
+                (v * max as f32).round() as u64
+            }
+        };
+        let half = |v: f32| if v <= 0.0 { 0 } else { f32_to_f16(v.min(1.0)) as u64 };
+        let Color { r, g, b, a } = *self;
+        Some(match format {
+            Argb8888 => unorm(a, 8) << 24 | unorm(r, 8) << 16 | unorm(g, 8) << 8 | unorm(b, 8),
+            Xrgb8888 => 0xFF << 24 | unorm(r, 8) << 16 | unorm(g, 8) << 8 | unorm(b, 8),
+            Abgr8888 => unorm(a, 8) << 24 | unorm(b, 8) << 16 | unorm(g, 8) << 8 | unorm(r, 8),
+            Xbgr8888 => 0xFF << 24 | unorm(b, 8) << 16 | unorm(g, 8) << 8 | unorm(r, 8),
+            Rgb565 => unorm(r, 5) << 11 | unorm(g, 6) << 5 | unorm(b, 5),
+            Abgr2101010 => {
+                unorm(a, 2) << 30 | unorm(b, 10) << 20 | unorm(g, 10) << 10 | unorm(r, 10)
+            }
+            Xbgr2101010 => 0x3 << 30 | unorm(b, 10) << 20 | unorm(g, 10) << 10 | unorm(r, 10),
+            Abgr16161616f => half(a) << 48 | half(b) << 32 | half(g) << 16 | half(r),
+            Xbgr16161616f => 0x3C00 << 48 | half(b) << 32 | half(g) << 16 | half(r),
+            _ => return None,
+        })
+    }
+}
+
+// Convert a non-negative, finite float to a IEEE 754 half-precision float
+fn f32_to_f16(v: f32) -> u16 {
+    let bits = v.to_bits();
+    let exp = ((bits >> 23) & 0xFF) as i32 - 127 + 15;
+    let mant = bits & 0x7F_FFFF;
+    if exp >= 0x1F {
+        // too large, saturate to infinity
+        0x7C00
+    } else if exp <= 0 {
+        // subnormal or zero
+        if exp < -10 {
+            return 0;
+        }
+        let mant = mant | 0x80_0000;
+        let shift = (14 - exp) as u32;
+        ((mant + (1 << (shift - 1))) >> shift) as u16
+    } else {
+        // rounding may carry into the exponent, which is the correct result
+        (((exp as u32) << 10) + ((mant + 0x1000) >> 13)) as u16
+    }
+}
+
+/// A typed view on the contents of a buffer
+///
+/// This gives access to the pixels of a buffer created by the typed methods of the pools,
+/// such as [`AutoMemPool::canvas_buffer`](struct.AutoMemPool.html#method.canvas_buffer).
+/// Pixels are written with the byte order of the format, whatever the endianness of the
+/// machine.
+pub struct Canvas<'a> {
+    data: &'a mut [u8],
+    width: u32,
+    height: u32,
+    stride: usize,
+    format: wl_shm::Format,
+}
+
+impl<'a> Canvas<'a> {
+    pub(crate) fn new(
+        data: &'a mut [u8],
+        width: u32,
+        height: u32,
+        stride: i32,
+        format: wl_shm::Format,
+    ) -> Canvas<'a> {
+        Canvas { data, width, height, stride: stride as usize, format }
+    }
+
+    /// The width of the buffer, in pixels
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height of the buffer, in pixels
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The stride of the buffer, in bytes
+    pub fn stride(&self) -> i32 {
+        self.stride as i32
+    }
+
+    /// The pixel format of the buffer
+    pub fn format(&self) -> wl_shm::Format {
+        self.format
+    }
+
+    fn bpp(&self) -> usize {
+        bytes_per_pixel(self.format).unwrap() as usize
+    }
+
+    /// Iterate over the rows of the buffer
+    ///
+    /// Each row contains exactly `width` pixels, without the padding of the stride.
+    pub fn rows_mut(&mut self) -> impl Iterator<Item = &mut [u8]> + '_ {
+        let row_len = self.width as usize * self.bpp();
+        self.data
+            .chunks_mut(self.stride)
+            .take(self.height as usize)
+            .map(move |row| &mut row[..row_len])
+    }
+
+    /// The raw contents of the buffer
+    pub fn data(&mut self) -> &mut [u8] {
+        self.data
+    }
+
+    /// Write a pixel value, as returned by [`Color::encode`](struct.Color.html#method.encode)
+    ///
+    /// Panics if the coordinates are outside of the buffer.
+    pub fn set_pixel(&mut self, x: u32, y: u32, value: u64) {
+        assert!(x < self.width && y < self.height, "Pixel out of bounds.");
+        let bpp = self.bpp();
+        let offset = y as usize * self.stride + x as usize * bpp;
+        self.data[offset..][..bpp].copy_from_slice(&value.to_le_bytes()[..bpp]);
+    }
+
+    /// Write the color of a pixel
+    ///
+    /// Panics if the coordinates are outside of the buffer.
+    pub fn set_color(&mut self, x: u32, y: u32, color: Color) {
+        self.set_pixel(x, y, color.encode(self.format).unwrap());
+    }
+
+    /// Fill the whole buffer with a color
+    pub fn fill(&mut self, color: Color) {
+        let bpp = self.bpp();
+        let value = color.encode(self.format).unwrap().to_le_bytes();
+        for row in self.rows_mut() {
+            for pixel in row.chunks_exact_mut(bpp) {
+                pixel.copy_from_slice(&value[..bpp]);
+            }
+        }
+    }
+}
+
+impl<'a> fmt::Debug for Canvas<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Canvas")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("stride", &self.stride)
+            .field("format", &self.format)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wl_shm::Format;
+
+    #[test]
+    fn check_buffer_size() {
+        assert_eq!(check_buffer(None, Format::Argb8888, 10, 5).unwrap(), 40);
+        assert!(matches!(
+            check_buffer(None, Format::Argb8888, 0, 5),
+            Err(CanvasError::InvalidSize { width: 0, height: 5 })
+        ));
+        assert!(matches!(
+            check_buffer(None, Format::Argb8888, 10, 0),
+            Err(CanvasError::InvalidSize { width: 10, height: 0 })
+        ));
+        assert!(matches!(
+            check_buffer(None, Format::Argb8888, 0x4000_0000, 1),
+            Err(CanvasError::InvalidSize { .. })
+        ));
+        assert!(matches!(
+            check_buffer(None, Format::Argb8888, 1, 0x8000_0000),
+            Err(CanvasError::InvalidSize { .. })
+        ));
+    }
+
+    #[test]
+    fn check_buffer_format() {
+        let advertised = [Format::Rgb565];
+        // Argb8888 and Xrgb8888 are always supported
+        assert!(check_buffer(Some(&[]), Format::Xrgb8888, 1, 1).is_ok());
+        assert_eq!(check_buffer(Some(&advertised), Format::Rgb565, 3, 1).unwrap(), 6);
+        assert!(matches!(
+            check_buffer(Some(&advertised), Format::Abgr8888, 1, 1),
+            Err(CanvasError::UnsupportedFormat(Format::Abgr8888))
+        ));
+        // without a list, known formats are assumed to be supported
+        assert!(check_buffer(None, Format::Abgr8888, 1, 1).is_ok());
+        assert!(matches!(
+            check_buffer(None, Format::Yuyv, 1, 1),
+            Err(CanvasError::UnknownFormat(Format::Yuyv))
+        ));
+    }
+
+    #[test]
+    fn stride_overflow() {
+        assert_eq!(stride(Format::Argb8888, 100), Some(400));
+        assert_eq!(stride(Format::Argb8888, 0x2000_0000), None);
+        assert_eq!(stride(Format::Abgr16161616f, 0x4000_0000), None);
+        assert_eq!(stride(Format::Yuyv, 100), None);
+    }
+}
@@ -1,26 +1,18 @@
 use std::{
-    cell::RefCell,
-    ffi::CStr,
+    cell::{Cell, RefCell},
     fmt,
     fs::File,
     io,
-    os::unix::io::{FromRawFd, RawFd},
-    rc::Rc,
-    time::SystemTime,
-    time::UNIX_EPOCH,
-};
-
-#[cfg(target_os = "linux")]
-use nix::sys::memfd;
-use nix::{
-    errno::Errno,
-    fcntl,
-    sys::{mman, stat},
-    unistd,
+    os::unix::io::AsRawFd,
+    rc::{Rc, Weak},
+    time::{Duration, Instant},
 };
 
 use memmap2::MmapMut;
 
+use super::file::{create_shm_file, shm_file_strategy};
+use super::format::{check_format, Canvas, CanvasError};
+
 use wayland_client::{
     protocol::{wl_buffer, wl_shm, wl_shm_pool},
     Attached, Main,
@@ -35,6 +27,10 @@ use wayland_client::{
 /// DoubleMemPool requires a implementation that is called when
 /// one of the two internal memory pools becomes free after None
 /// was returned from the `pool()` method.
+///
+/// This type is deprecated in favor of [`Swapchain`](struct.Swapchain.html), which supports
+/// more than two buffers, tracks their age and sizes them for you.
+#[deprecated(since = "0.16.0", note = "use `Swapchain` instead")]
 #[derive(Debug)]
 pub struct DoubleMemPool {
     pool1: MemPool,
@@ -42,6 +38,7 @@ pub struct DoubleMemPool {
     free: Rc<RefCell<bool>>,
 }
 
+#[allow(deprecated)]
 impl DoubleMemPool {
     /// Create a double memory pool
     pub fn new<F>(shm: Attached<wl_shm::WlShm>, callback: F) -> io::Result<DoubleMemPool>
@@ -100,8 +97,116 @@ impl DoubleMemPool {
     }
 }
 
+/// A policy for returning the memory of a pool to the system
+///
+/// Memory pools only grow as long as they are in use: the shared memory file cannot be
+/// shrunk once the compositor has mapped it. With a shrink policy set, a pool whose
+/// usage has stayed below `threshold` times its size for at least `delay` replaces its
+/// backing file with a smaller one, the next time it needs memory while none of its buffers
+/// are in use by the compositor.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ShrinkPolicy {
+    /// Fraction of the pool size the usage must stay below
+    pub threshold: f32,
+    /// How long the usage must stay below the threshold
+    pub delay: Duration,
+}
+
+impl Default for ShrinkPolicy {
+    fn default() -> ShrinkPolicy {
+        ShrinkPolicy { threshold: 0.25, delay: Duration::from_secs(10) }
+    }
+}
+
+/// Memory usage statistics of a pool
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PoolMetrics {
+    /// Current size of the pool, in bytes
+    pub size: usize,
+    /// Largest size the pool ever had, in bytes
+    pub high_water: usize,
+    /// Number of bytes currently allocated to buffers
+    pub used: usize,
+    /// Number of distinct free blocks
+    pub free_blocks: usize,
+    /// Size of the largest free block, in bytes
+    pub largest_free_block: usize,
+    /// Fragmentation of the free memory, between 0 (a single free block) and 1
+    ///
+    /// This is `1 - largest_free_block / free_memory`.
+    pub fragmentation: f32,
+}
+
+impl PoolMetrics {
+    fn new(size: usize, high_water: usize, free_list: &[(usize, usize)]) -> PoolMetrics {
+        let free: usize = free_list.iter().map(|&(_, len)| len).sum();
+        let largest_free_block = free_list.iter().map(|&(_, len)| len).max().unwrap_or(0);
+        PoolMetrics {
+            size,
+            high_water,
+            used: size - free,
+            free_blocks: free_list.iter().filter(|&&(_, len)| len > 0).count(),
+            largest_free_block,
+            fragmentation: if free == 0 {
+                0.0
+            } else {
+                1.0 - largest_free_block as f32 / free as f32
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ShrinkState {
+    policy: Option<ShrinkPolicy>,
+    high_water: usize,
+    below_since: Option<Instant>,
+    peak: usize,
+}
+
+impl ShrinkState {
+    fn new(size: usize) -> ShrinkState {
+        ShrinkState { policy: None, high_water: size, below_since: None, peak: 0 }
+    }
+
+    fn record(&mut self, used: usize, size: usize) {
+        self.high_water = std::cmp::max(self.high_water, size);
+        let policy = match self.policy {
+            Some(policy) => policy,
+            None => return,
+        };
+        if used as f32 > policy.threshold * size as f32 {
+            self.below_since = None;
+        } else if self.below_since.is_none() {
+            self.below_since = Some(Instant::now());
+            self.peak = used;
+        } else {
+            self.peak = std::cmp::max(self.peak, used);
+        }
+    }
+
+    // The size the pool should be shrunk to, if any
+    fn target(&self, size: usize) -> Option<usize> {
+        let policy = self.policy?;
+        if self.below_since?.elapsed() < policy.delay {
+            return None;
+        }
+        let target = std::cmp::max((self.peak + 4095) & !4095, 4096);
+        if target < size {
+            Some(target)
+        } else {
+            None
+        }
+    }
+
+    fn shrunk(&mut self) {
+        self.below_since = None;
+    }
+}
+
 #[derive(Debug)]
 struct Inner {
+    shm: Attached<wl_shm::WlShm>,
     file: File,
     len: usize,
     pool: Main<wl_shm_pool::WlShmPool>,
@@ -110,15 +215,24 @@ struct Inner {
 
 impl Inner {
     fn new(shm: Attached<wl_shm::WlShm>) -> io::Result<Self> {
-        let mem_fd = create_shm_fd()?;
-        let mem_file = unsafe { File::from_raw_fd(mem_fd) };
-        mem_file.set_len(4096)?;
+        Self::with_size(shm, 4096)
+    }
+
+    fn with_size(shm: Attached<wl_shm::WlShm>, len: usize) -> io::Result<Self> {
+        let mem_file = create_shm_file(shm_file_strategy())?;
+        mem_file.set_len(len as u64)?;
 
-        let pool = shm.create_pool(mem_fd, 4096);
+        let pool = shm.create_pool(mem_file.as_raw_fd(), len as i32);
 
         let mmap = unsafe { MmapMut::map_mut(&mem_file).unwrap() };
 
-        Ok(Inner { file: mem_file, len: 4096, pool, mmap })
+        Ok(Inner { shm, file: mem_file, len, pool, mmap })
+    }
+
+    // Replace the backing file with a new one, the pool must not be in use
+    fn replace(&mut self, len: usize) -> io::Result<()> {
+        *self = Self::with_size(self.shm.clone(), len)?;
+        Ok(())
     }
 
     fn resize(&mut self, newsize: usize) -> io::Result<()> {
@@ -159,6 +273,8 @@ pub struct MemPool {
     inner: Inner,
     buffer_count: Rc<RefCell<u32>>,
     callback: Rc<RefCell<dyn FnMut(wayland_client::DispatchData)>>,
+    shrink: ShrinkState,
+    requested: usize,
 }
 
 impl MemPool {
@@ -171,6 +287,8 @@ impl MemPool {
             inner: Inner::new(shm)?,
             buffer_count: Rc::new(RefCell::new(0)),
             callback: Rc::new(RefCell::new(callback)),
+            shrink: ShrinkState::new(4096),
+            requested: 0,
         })
     }
 
@@ -181,12 +299,49 @@ impl MemPool {
     /// call this method otherwise the server won't see the new size.
     ///
     /// Memory pools can only be extented, as such this method will do nothing
-    /// if the requested new size is smaller than the current size.
+    /// if the requested new size is smaller than the current size, unless a
+    /// [`ShrinkPolicy`](struct.ShrinkPolicy.html) is set.
     ///
     /// This method allows you to ensure the underlying pool is large enough to
     /// hold what you want to write to it.
+    ///
+    /// If a shrink policy is set and the pool is not in use, this method may replace
+    /// the shared memory file with a smaller one: its previous contents are then lost,
+    /// so you should call it before writing the contents of your buffers.
     pub fn resize(&mut self, newsize: usize) -> io::Result<()> {
-        self.inner.resize(newsize)
+        self.requested = newsize;
+        self.shrink.record(newsize, self.inner.len);
+        if !self.is_used() {
+            if let Some(target) = self.shrink.target(self.inner.len) {
+                let target = std::cmp::max(target, newsize);
+                if target < self.inner.len {
+                    self.inner.replace(target)?;
+                    self.shrink.shrunk();
+                }
+            }
+        }
+        self.inner.resize(newsize)?;
+        self.shrink.record(newsize, self.inner.len);
+        Ok(())
+    }
+
+    /// Set the policy for returning unused memory to the system
+    ///
+    /// For a `MemPool`, the usage is the size last requested with `resize()`. The default
+    /// is `None`: the pool never shrinks.
+    pub fn set_shrink_policy(&mut self, policy: Option<ShrinkPolicy>) {
+        self.shrink.policy = policy;
+        self.shrink.below_since = None;
+    }
+
+    /// Memory usage statistics of this pool
+    ///
+    /// As a `MemPool` does not manage the placement of buffers, `used` is the size
+    /// last requested with `resize()`, and the remaining memory is reported as a single
+    /// free block.
+    pub fn metrics(&self) -> PoolMetrics {
+        let used = std::cmp::min(self.requested, self.inner.len);
+        PoolMetrics::new(self.inner.len, self.shrink.high_water, &[(used, self.inner.len - used)])
     }
 
     /// Create a new buffer to this pool
@@ -232,6 +387,31 @@ impl MemPool {
         (*buffer).clone().detach()
     }
 
+    /// Create a new buffer in this pool, with a typed view on its contents
+    ///
+    /// The pool is grown if needed to hold the buffer at given `offset`, and the
+    /// stride is computed from the format. The format is checked against the list of
+    /// formats advertised by the compositor, see
+    /// [`is_format_supported`](fn.is_format_supported.html), and a negative `offset`
+    /// is rejected.
+    pub fn canvas_buffer(
+        &mut self,
+        offset: i32,
+        width: u32,
+        height: u32,
+        format: wl_shm::Format,
+    ) -> Result<(Canvas<'_>, wl_buffer::WlBuffer), CanvasError> {
+        if offset < 0 {
+            return Err(CanvasError::InvalidOffset(offset));
+        }
+        let stride = check_format(&self.inner.shm, format, width, height)?;
+        let len = (height as usize) * (stride as usize);
+        self.resize(offset as usize + len)?;
+        let buffer = self.buffer(offset, width as i32, height as i32, stride, format);
+        let data = &mut self.inner.mmap[offset as usize..][..len];
+        Ok((Canvas::new(data, width, height, stride, format), buffer))
+    }
+
     /// Uses the memmap2 crate to map the underlying shared memory file
     pub fn mmap(&mut self) -> &mut MmapMut {
         &mut self.inner.mmap
@@ -249,6 +429,8 @@ impl fmt::Debug for MemPool {
             .field("inner", &self.inner)
             .field("buffer_count", &self.buffer_count)
             .field("callback", &"Fn() -> { ... }")
+            .field("shrink", &self.shrink)
+            .field("requested", &self.requested)
             .finish()
     }
 }
@@ -282,11 +464,17 @@ impl io::Seek for MemPool {
 ///
 /// The default alignment of returned buffers is 16 bytes; this can be changed by using the
 /// explicit with_min_align constructor.
+///
+/// Besides the one-shot buffers returned by `buffer()`, the pool can hand out long-lived
+/// [`Slot`](struct.Slot.html)s, which keep their memory and their `wl_buffer` across frames.
 #[derive(Debug)]
 pub struct AutoMemPool {
     inner: Inner,
     align: usize,
     free_list: Rc<RefCell<Vec<(usize, usize)>>>,
+    slots: Vec<Weak<SlotInner>>,
+    frame: u64,
+    shrink: ShrinkState,
 }
 
 impl AutoMemPool {
@@ -304,7 +492,8 @@ impl AutoMemPool {
         assert!(align <= 4096);
         let inner = Inner::new(shm)?;
         let free_list = Rc::new(RefCell::new(vec![(0, inner.len)]));
-        Ok(AutoMemPool { inner, align, free_list })
+        let shrink = ShrinkState::new(inner.len);
+        Ok(AutoMemPool { inner, align, free_list, slots: Vec::new(), frame: 0, shrink })
     }
 
     /// Resize the memory pool
@@ -316,6 +505,7 @@ impl AutoMemPool {
             return Ok(());
         }
         self.inner.resize(new_size)?;
+        self.shrink.high_water = std::cmp::max(self.shrink.high_water, new_size);
         // add the new memory to the freelist
         let mut free = self.free_list.borrow_mut();
         if let Some((off, len)) = free.last_mut() {
@@ -328,7 +518,56 @@ impl AutoMemPool {
         Ok(())
     }
 
+    /// Set the policy for returning unused memory to the system
+    ///
+    /// The pool can only be shrunk when no memory of it is allocated, meaning that all
+    /// its buffers have been released by the compositor and all its slots have been dropped.
+    /// The default is `None`: the pool never shrinks.
+    pub fn set_shrink_policy(&mut self, policy: Option<ShrinkPolicy>) {
+        self.shrink.policy = policy;
+        self.shrink.below_since = None;
+    }
+
+    /// Shrink the pool now if its shrink policy allows it
+    ///
+    /// This is done automatically when new memory is allocated from the pool, but can be
+    /// used to release memory while the application is idle. Returns whether the pool
+    /// was shrunk.
+    pub fn try_shrink(&mut self) -> io::Result<bool> {
+        let used = self.used();
+        self.shrink.record(used, self.inner.len);
+        if used != 0 {
+            return Ok(false);
+        }
+        match self.shrink.target(self.inner.len) {
+            Some(target) => {
+                self.inner.replace(target)?;
+                *self.free_list.borrow_mut() = vec![(0, target)];
+                self.slots.clear();
+                self.shrink.shrunk();
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Memory usage statistics of this pool
+    pub fn metrics(&self) -> PoolMetrics {
+        PoolMetrics::new(self.inner.len, self.shrink.high_water, &self.free_list.borrow())
+    }
+
+    fn used(&self) -> usize {
+        self.inner.len - self.free_list.borrow().iter().map(|&(_, len)| len).sum::<usize>()
+    }
+
     fn alloc(&mut self, size: usize) -> io::Result<usize> {
+        self.try_shrink()?;
+        let offset = self.alloc_inner(size)?;
+        self.shrink.record(self.used(), self.inner.len);
+        Ok(offset)
+    }
+
+    fn alloc_inner(&mut self, size: usize) -> io::Result<usize> {
         let mut free = self.free_list.borrow_mut();
         for (offset, len) in free.iter_mut() {
             if *len >= size {
@@ -359,31 +598,8 @@ impl AutoMemPool {
         Ok(rv)
     }
 
-    fn free(free_list: &RefCell<Vec<(usize, usize)>>, mut offset: usize, mut len: usize) {
-        let mut free = free_list.borrow_mut();
-        let mut nf = Vec::with_capacity(free.len() + 1);
-        for &(ioff, ilen) in free.iter() {
-            if ioff + ilen == offset {
-                offset = ioff;
-                len += ilen;
-                continue;
-            }
-            if ioff == offset + len {
-                len += ilen;
-                continue;
-            }
-            if ioff > offset + len && len != 0 {
-                nf.push((offset, len));
-                len = 0;
-            }
-            if ilen != 0 {
-                nf.push((ioff, ilen));
-            }
-        }
-        if len != 0 {
-            nf.push((offset, len));
-        }
-        *free = nf;
+    fn free(free_list: &RefCell<Vec<(usize, usize)>>, offset: usize, len: usize) {
+        free_block(&mut free_list.borrow_mut(), offset, len);
     }
 
     /// Create a new buffer in this pool
@@ -419,6 +635,22 @@ impl AutoMemPool {
         Ok((&mut self.inner.mmap[offset..][..len], buffer.detach()))
     }
 
+    /// Create a new buffer in this pool, with a typed view on its contents
+    ///
+    /// This is identical to buffer(), but the stride is computed from the format, and
+    /// the format is checked against the list of formats advertised by the compositor,
+    /// see [`is_format_supported`](fn.is_format_supported.html).
+    pub fn canvas_buffer(
+        &mut self,
+        width: u32,
+        height: u32,
+        format: wl_shm::Format,
+    ) -> Result<(Canvas<'_>, wl_buffer::WlBuffer), CanvasError> {
+        let stride = check_format(&self.inner.shm, format, width, height)?;
+        let (data, buffer) = self.buffer(width as i32, height as i32, stride, format)?;
+        Ok((Canvas::new(data, width, height, stride, format), buffer))
+    }
+
     /// Try drawing with the given closure
     ///
     /// This is identical to buffer(), but will only actually create the WlBuffer if the draw
@@ -455,66 +687,300 @@ impl AutoMemPool {
         });
         Ok(buffer.detach())
     }
+
+    /// Allocate a new slot of at least `len` bytes in this pool
+    ///
+    /// The memory of the slot is reserved until the slot is dropped and all the buffers
+    /// created from it have been released by the compositor. Its contents are initially
+    /// undefined, so its [`damage()`](struct.Slot.html#method.damage) covers all of it.
+    pub fn new_slot(&mut self, len: usize) -> io::Result<Slot> {
+        let len = (len + self.align - 1) & !(self.align - 1);
+        let offset = self.alloc(len)?;
+        let inner = Rc::new(SlotInner {
+            state: Rc::new(SlotState {
+                offset,
+                len,
+                free_list: self.free_list.clone(),
+                active_buffers: Cell::new(0),
+                generation: Cell::new(0),
+                orphaned: Cell::new(false),
+                last_shown: Cell::new(0),
+                damage: RefCell::new(None),
+            }),
+            buffer: RefCell::new(None),
+        });
+        self.slots.retain(|s| s.strong_count() > 0);
+        self.slots.push(Rc::downgrade(&inner));
+        Ok(Slot { inner })
+    }
+
+    /// Access the contents of a slot for drawing
+    ///
+    /// Returns `None` if the slot is currently held by the compositor, as modifying its
+    /// contents could then cause graphical glitches.
+    ///
+    /// Panics if the slot was not created from this pool.
+    pub fn slot_canvas(&mut self, slot: &Slot) -> Option<&mut [u8]> {
+        let state = &slot.inner.state;
+        assert!(Rc::ptr_eq(&state.free_list, &self.free_list), "Slot used with a different pool.");
+        if slot.is_busy() {
+            return None;
+        }
+        Some(&mut self.inner.mmap[state.offset..][..state.len])
+    }
+
+    /// Get a buffer showing the current contents of a slot
+    ///
+    /// The parameters are the same as for `buffer()`. The `wl_buffer` is kept by the slot
+    /// and returned again by later calls with the same parameters, it is only recreated
+    /// when they change. The slot is considered busy from this call until the compositor
+    /// releases the buffer, so the returned buffer should be attached to a surface.
+    ///
+    /// This marks the slot as shown: its accumulated damage is cleared. Call
+    /// [`damage_slots()`](#method.damage_slots) with the damage of the new frame *before*
+    /// this method, so that the other slots of the pool record it.
+    ///
+    /// Panics if the slot was not created from this pool.
+    pub fn slot_buffer(
+        &mut self,
+        slot: &Slot,
+        width: i32,
+        height: i32,
+        stride: i32,
+        format: wl_shm::Format,
+    ) -> io::Result<wl_buffer::WlBuffer> {
+        let state = &slot.inner.state;
+        assert!(Rc::ptr_eq(&state.free_list, &self.free_list), "Slot used with a different pool.");
+        if width <= 0
+            || height <= 0
+            || stride < 0
+            || (height as usize) * (stride as usize) > state.len
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Buffer parameters do not fit in the slot.",
+            ));
+        }
+
+        let mut current = slot.inner.buffer.borrow_mut();
+        let reusable = match *current {
+            Some(ref b) => {
+                (b.width, b.height, b.stride, b.format) == (width, height, stride, format)
+            }
+            None => false,
+        };
+        if !reusable {
+            if let Some(old) = current.take() {
+                // a busy buffer is destroyed by its release handler
+                if !old.busy.get() {
+                    old.buffer.destroy();
+                }
+            }
+            let generation = state.generation.get().wrapping_add(1);
+            state.generation.set(generation);
+            let busy = Rc::new(Cell::new(false));
+            let buffer =
+                self.inner.pool.create_buffer(state.offset as i32, width, height, stride, format);
+            let my_state = state.clone();
+            let my_busy = busy.clone();
+            buffer.quick_assign(move |buffer, event, _| match event {
+                wl_buffer::Event::Release => my_state.release(&buffer, &my_busy, generation),
+                _ => unreachable!(),
+            });
+            *current =
+                Some(SlotBuffer { width, height, stride, format, busy, buffer: buffer.detach() });
+        }
+
+        let current = current.as_ref().unwrap();
+        if !current.busy.get() {
+            current.busy.set(true);
+            state.active_buffers.set(state.active_buffers.get() + 1);
+        }
+        self.frame += 1;
+        state.last_shown.set(self.frame);
+        *state.damage.borrow_mut() = Some(Vec::new());
+        Ok(current.buffer.clone())
+    }
+
+    /// Record damage on all the slots of this pool
+    ///
+    /// This should be called with the damaged area of each new frame, in buffer coordinates,
+    /// so that each slot tracks what changed since it was last shown.
+    pub fn damage_slots(&self, x: i32, y: i32, width: i32, height: i32) {
+        for slot in self.slots.iter().filter_map(Weak::upgrade) {
+            slot.state.add_damage(x, y, width, height);
+        }
+    }
+
+    /// Find the slot of this pool that was shown the longest time ago and is not busy
+    ///
+    /// Only slots of at least `len` bytes are considered. Slots that were never shown
+    /// are returned first.
+    pub fn oldest_free_slot(&self, len: usize) -> Option<Slot> {
+        self.slots
+            .iter()
+            .filter_map(Weak::upgrade)
+            .filter(|s| s.state.len >= len && s.state.active_buffers.get() == 0)
+            .min_by_key(|s| s.state.last_shown.get())
+            .map(|inner| Slot { inner })
+    }
+}
+
+/// A region of an [`AutoMemPool`](struct.AutoMemPool.html) reused across frames
+///
+/// Unlike the buffers returned by `AutoMemPool::buffer()`, a slot keeps its memory and its
+/// `wl_buffer` once the compositor has released it, so its contents can be updated
+/// incrementally. Each slot accumulates the damage recorded on the pool with
+/// `AutoMemPool::damage_slots()` since it was last shown, so an application keeping
+/// several slots can reuse the oldest free one and only repaint that damage.
+///
+/// Slots are reference-counted handles: cloning a `Slot` does not allocate new memory.
+/// The memory returns to the pool once all handles are dropped and the compositor has
+/// released all the buffers of the slot. Do not call `destroy()` on these buffers.
+#[derive(Debug, Clone)]
+pub struct Slot {
+    inner: Rc<SlotInner>,
+}
+
+#[derive(Debug)]
+struct SlotInner {
+    state: Rc<SlotState>,
+    buffer: RefCell<Option<SlotBuffer>>,
+}
+
+#[derive(Debug)]
+struct SlotBuffer {
+    width: i32,
+    height: i32,
+    stride: i32,
+    format: wl_shm::Format,
+    busy: Rc<Cell<bool>>,
+    buffer: wl_buffer::WlBuffer,
 }
 
-fn create_shm_fd() -> io::Result<RawFd> {
-    // Only try memfd on linux
-    #[cfg(target_os = "linux")]
-    loop {
-        match memfd::memfd_create(
-            CStr::from_bytes_with_nul(b"smithay-client-toolkit\0").unwrap(),
-            memfd::MemFdCreateFlag::MFD_CLOEXEC | memfd::MemFdCreateFlag::MFD_ALLOW_SEALING,
-        ) {
-            Ok(fd) => {
-                // this is only an optimization, so ignore errors
-                let _ = fcntl::fcntl(
-                    fd,
-                    fcntl::F_ADD_SEALS(
-                        fcntl::SealFlag::F_SEAL_SHRINK | fcntl::SealFlag::F_SEAL_SEAL,
-                    ),
-                );
-                return Ok(fd);
+// the state shared between a slot and the release handlers of its buffers
+#[derive(Debug)]
+struct SlotState {
+    offset: usize,
+    len: usize,
+    free_list: Rc<RefCell<Vec<(usize, usize)>>>,
+    active_buffers: Cell<usize>,
+    generation: Cell<u32>,
+    orphaned: Cell<bool>,
+    last_shown: Cell<u64>,
+    // `None` means the whole slot is damaged
+    damage: RefCell<Option<Vec<DamageRect>>>,
+}
+
+type DamageRect = (i32, i32, i32, i32);
+
+// past this number of rectangles, the damage of a slot is merged into its bounding box
+const MAX_SLOT_DAMAGE: usize = 16;
+
+impl SlotState {
+    fn release(&self, buffer: &wl_buffer::WlBuffer, busy: &Cell<bool>, generation: u32) {
+        if busy.replace(false) {
+            self.active_buffers.set(self.active_buffers.get() - 1);
+        }
+        if self.orphaned.get() || self.generation.get() != generation {
+            buffer.destroy();
+        }
+        if self.orphaned.get() && self.active_buffers.get() == 0 {
+            AutoMemPool::free(&self.free_list, self.offset, self.len);
+        }
+    }
+
+    fn add_damage(&self, x: i32, y: i32, width: i32, height: i32) {
+        let mut damage = self.damage.borrow_mut();
+        if let Some(ref mut rects) = *damage {
+            rects.push((x, y, width, height));
+            if rects.len() > MAX_SLOT_DAMAGE {
+                let (x, y, w, h) = rects[0];
+                let (mut x1, mut y1, mut x2, mut y2) =
+                    (x, y, x.saturating_add(w), y.saturating_add(h));
+                for &(x, y, w, h) in rects.iter() {
+                    x1 = x1.min(x);
+                    y1 = y1.min(y);
+                    x2 = x2.max(x.saturating_add(w));
+                    y2 = y2.max(y.saturating_add(h));
+                }
+                *rects = vec![(x1, y1, x2 - x1, y2 - y1)];
             }
-            Err(Errno::EINTR) => continue,
-            Err(Errno::ENOSYS) => break,
-            Err(errno) => return Err(errno.into()),
-        }
-    }
-
-    // Fallback to using shm_open
-    let sys_time = SystemTime::now();
-    let mut mem_file_handle = format!(
-        "/smithay-client-toolkit-{}",
-        sys_time.duration_since(UNIX_EPOCH).unwrap().subsec_nanos()
-    );
-    loop {
-        match mman::shm_open(
-            mem_file_handle.as_str(),
-            fcntl::OFlag::O_CREAT
-                | fcntl::OFlag::O_EXCL
-                | fcntl::OFlag::O_RDWR
-                | fcntl::OFlag::O_CLOEXEC,
-            stat::Mode::S_IRUSR | stat::Mode::S_IWUSR,
-        ) {
-            Ok(fd) => match mman::shm_unlink(mem_file_handle.as_str()) {
-                Ok(_) => return Ok(fd),
-                Err(errno) => match unistd::close(fd) {
-                    Ok(_) => return Err(errno.into()),
-                    Err(errno) => return Err(errno.into()),
-                },
-            },
-            Err(Errno::EEXIST) => {
-                // If a file with that handle exists then change the handle
-                mem_file_handle = format!(
-                    "/smithay-client-toolkit-{}",
-                    sys_time.duration_since(UNIX_EPOCH).unwrap().subsec_nanos()
-                );
-                continue;
+        }
+    }
+}
+
+impl Slot {
+    /// The size of this slot, in bytes
+    pub fn size(&self) -> usize {
+        self.inner.state.len
+    }
+
+    /// Whether this slot is currently held by the compositor
+    ///
+    /// A busy slot cannot be drawn to.
+    pub fn is_busy(&self) -> bool {
+        self.inner.state.active_buffers.get() > 0
+    }
+
+    /// The damage accumulated on this slot since it was last shown
+    ///
+    /// These are the `(x, y, width, height)` rectangles, in buffer coordinates, that need
+    /// to be repainted to bring this slot up to date. `None` means the whole slot needs to be
+    /// repainted, which is the case if it was never shown.
+    pub fn damage(&self) -> Option<Vec<(i32, i32, i32, i32)>> {
+        self.inner.state.damage.borrow().clone()
+    }
+
+    /// Add damage to this slot only
+    ///
+    /// This can be used to force parts of the slot to be repainted the next time it is used.
+    pub fn add_damage(&self, x: i32, y: i32, width: i32, height: i32) {
+        self.inner.state.add_damage(x, y, width, height);
+    }
+}
+
+impl Drop for SlotInner {
+    fn drop(&mut self) {
+        self.state.orphaned.set(true);
+        if let Some(current) = self.buffer.borrow_mut().take() {
+            // a busy buffer is destroyed by its release handler
+            if !current.busy.get() {
+                current.buffer.destroy();
             }
-            Err(Errno::EINTR) => continue,
-            Err(errno) => return Err(errno.into()),
         }
+        if self.state.active_buffers.get() == 0 {
+            AutoMemPool::free(&self.state.free_list, self.state.offset, self.state.len);
+        }
+        // otherwise the release handlers of the busy buffers will free the memory
+    }
+}
+
+// Return a block of memory to a free list, merging it with the adjacent free blocks
+pub(super) fn free_block(free: &mut Vec<(usize, usize)>, mut offset: usize, mut len: usize) {
+    let mut nf = Vec::with_capacity(free.len() + 1);
+    for &(ioff, ilen) in free.iter() {
+        if ioff + ilen == offset {
+            offset = ioff;
+            len += ilen;
+            continue;
+        }
+        if ioff == offset + len {
+            len += ilen;
+            continue;
+        }
+        if ioff > offset + len && len != 0 {
+            nf.push((offset, len));
+            len = 0;
+        }
+        if ilen != 0 {
+            nf.push((ioff, ilen));
+        }
+    }
+    if len != 0 {
+        nf.push((offset, len));
     }
+    *free = nf;
 }
 
 impl<E> crate::environment::Environment<E>
@@ -540,6 +1006,8 @@ where
     ///
     /// The provided callback is triggered when one of the pools becomes unused again
     /// after you tried to draw while both where in use.
+    #[deprecated(since = "0.16.0", note = "use `create_swapchain` instead")]
+    #[allow(deprecated)]
     pub fn create_double_pool<F>(&self, callback: F) -> io::Result<DoubleMemPool>
     where
         F: FnMut(wayland_client::DispatchData) + 'static,
@@ -7,9 +7,22 @@ use wayland_client::{
     Attached, DispatchData,
 };
 
+mod file;
+mod format;
 mod mempool;
+mod solid;
+mod swapchain;
+mod sync_pool;
 
-pub use self::mempool::{AutoMemPool, DoubleMemPool, MemPool};
+pub use self::file::{
+    create_shm_file, set_shm_file_strategy, shm_file_strategy, ShmFileError, ShmFileStrategy,
+};
+pub use self::format::{bytes_per_pixel, is_format_supported, stride, Canvas, CanvasError, Color};
+#[allow(deprecated)]
+pub use self::mempool::{AutoMemPool, DoubleMemPool, MemPool, PoolMetrics, ShrinkPolicy, Slot};
+pub use self::solid::SolidColorBuffers;
+pub use self::swapchain::{BufferAvailable, Swapchain, SwapchainBuffer};
+pub use self::sync_pool::{RenderTarget, SyncMemPool, SyncMemPoolHandle};
 pub use wl_shm::Format;
 
 /// A handler for the `wl_shm` global
@@ -46,6 +59,9 @@ impl crate::environment::GlobalHandler<wl_shm::WlShm> for ShmHandler {
             }
             _ => unreachable!(),
         });
+        // make the formats available to the pools created from this global
+        let formats: format::ShmFormats = self.formats.clone();
+        shm.as_ref().user_data().set(move || formats);
         self.shm = Some((*shm).clone());
     }
     fn get(&self) -> Option<Attached<wl_shm::WlShm>> {
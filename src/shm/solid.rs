@@ -0,0 +1,127 @@
+use std::io;
+
+use wayland_client::{
+    protocol::{wl_buffer, wl_shm, wl_surface},
+    Attached,
+};
+use wayland_protocols::viewporter::client::wp_viewport::WpViewport;
+
+use super::{AutoMemPool, Color, Slot};
+use crate::protocols::staging::single_pixel_buffer::v1::client::wp_single_pixel_buffer_manager_v1::WpSinglePixelBufferManagerV1;
+
+#[derive(Debug)]
+struct SolidBuffer {
+    color: u32,
+    buffer: wl_buffer::WlBuffer,
+    // keeps the memory of SHM buffers alive
+    _slot: Option<Slot>,
+}
+
+/// A cache of 1x1 buffers of a single color
+///
+/// Filling a rectangle with one color does not require a buffer of the size of the
+/// rectangle: a 1x1 buffer scaled with a `wp_viewport` is enough. These buffers are created
+/// with `wp_single_pixel_buffer_manager_v1` when the compositor supports it, and are
+/// otherwise 1x1 SHM buffers. They are cached per color, and shared by all the surfaces
+/// they are attached to.
+///
+/// Colors are given as `0xAARRGGBB` values with premultiplied alpha.
+#[derive(Debug)]
+pub struct SolidColorBuffers {
+    shm: Attached<wl_shm::WlShm>,
+    single_pixel: Option<Attached<WpSinglePixelBufferManagerV1>>,
+    pool: Option<AutoMemPool>,
+    buffers: Vec<SolidBuffer>,
+}
+
+impl SolidColorBuffers {
+    /// Create a new cache
+    ///
+    /// SHM buffers are used if no single-pixel buffer manager is provided.
+    pub fn new(
+        shm: Attached<wl_shm::WlShm>,
+        single_pixel: Option<Attached<WpSinglePixelBufferManagerV1>>,
+    ) -> SolidColorBuffers {
+        SolidColorBuffers { shm, single_pixel, pool: None, buffers: Vec::new() }
+    }
+
+    /// Get a 1x1 buffer of given color
+    ///
+    /// The buffer is owned by the cache, do not destroy it.
+    pub fn buffer(&mut self, color: u32) -> io::Result<wl_buffer::WlBuffer> {
+        if let Some(solid) = self.buffers.iter().find(|b| b.color == color) {
+            return Ok(solid.buffer.clone());
+        }
+
+        let solid = if let Some(ref manager) = self.single_pixel {
+            let channel = |shift: u32| ((color >> shift) & 0xFF) * 0x0101_0101;
+            let buffer =
+                manager.create_u32_rgba_buffer(channel(16), channel(8), channel(0), channel(24));
+            buffer.quick_assign(|_, _, _| {});
+            SolidBuffer { color, buffer: buffer.detach(), _slot: None }
+        } else {
+            if self.pool.is_none() {
+                self.pool = Some(AutoMemPool::new(self.shm.clone())?);
+            }
+            let pool = self.pool.as_mut().unwrap();
+            let slot = pool.new_slot(4)?;
+            let format = wl_shm::Format::Argb8888;
+            let pixel = Color::from_argb8888(color).encode(format).unwrap() as u32;
+            pool.slot_canvas(&slot).unwrap()[..4].copy_from_slice(&pixel.to_le_bytes());
+            let buffer = pool.slot_buffer(&slot, 1, 1, 4, format)?;
+            SolidBuffer { color, buffer, _slot: Some(slot) }
+        };
+        let buffer = solid.buffer.clone();
+        self.buffers.push(solid);
+        Ok(buffer)
+    }
+
+    /// Fill a surface with a color
+    ///
+    /// This attaches a 1x1 buffer of given color to the surface, scales it to `width` x `height`
+    /// (in surface coordinates) with the viewport of the surface, and damages the surface.
+    /// The buffer scale of the surface must be 1. You still need to commit the surface.
+    pub fn fill_surface(
+        &mut self,
+        surface: &wl_surface::WlSurface,
+        viewport: &WpViewport,
+        width: i32,
+        height: i32,
+        color: u32,
+    ) -> io::Result<()> {
+        viewport.set_destination(width, height);
+        let buffer = self.buffer(color)?;
+        surface.attach(Some(&buffer), 0, 0);
+        if surface.as_ref().version() >= 4 {
+            surface.damage_buffer(0, 0, 1, 1);
+        } else {
+            surface.damage(0, 0, width, height);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SolidColorBuffers {
+    fn drop(&mut self) {
+        for solid in self.buffers.drain(..) {
+            // SHM buffers are destroyed along with their slot
+            if solid._slot.is_none() {
+                solid.buffer.destroy();
+            }
+        }
+    }
+}
+
+impl<E> crate::environment::Environment<E>
+where
+    E: crate::environment::GlobalHandler<wl_shm::WlShm>
+        + crate::environment::GlobalHandler<WpSinglePixelBufferManagerV1>,
+{
+    /// Create a cache of solid color buffers
+    ///
+    /// It uses the `wp_single_pixel_buffer_manager_v1` global if it is available, and SHM
+    /// buffers otherwise.
+    pub fn create_solid_color_buffers(&self) -> SolidColorBuffers {
+        SolidColorBuffers::new(self.require_global(), self.get_global())
+    }
+}
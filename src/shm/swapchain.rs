@@ -0,0 +1,238 @@
+use std::{
+    cell::{Cell, RefCell},
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use wayland_client::{
+    protocol::{wl_buffer, wl_shm},
+    Attached,
+};
+
+use super::{format::check_format, Canvas, CanvasError, MemPool, ShrinkPolicy};
+
+// buffers are reallocated when their size drops below this fraction of their pool
+const REALLOC_THRESHOLD: f32 = 0.5;
+
+#[derive(Debug)]
+struct SwapState {
+    count: usize,
+    busy: Cell<usize>,
+    wakers: RefCell<Vec<Waker>>,
+}
+
+impl SwapState {
+    fn release(&self) {
+        self.busy.set(self.busy.get() - 1);
+        for waker in self.wakers.borrow_mut().drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+#[derive(Debug)]
+struct SwapSlot {
+    pool: MemPool,
+    // the frame this slot was last handed out for, if its contents are still valid
+    last_frame: Option<u64>,
+}
+
+/// A buffer handed out by a [`Swapchain`](struct.Swapchain.html)
+#[derive(Debug)]
+pub struct SwapchainBuffer<'a> {
+    /// A view on the contents of the buffer
+    pub canvas: Canvas<'a>,
+    /// The buffer, to attach to the surface
+    pub buffer: wl_buffer::WlBuffer,
+    /// The age of the contents of the buffer
+    ///
+    /// An age of `n > 0` means the buffer contains the frame drawn `n` frames ago, so only
+    /// the parts that changed since then need to be repainted. An age of 0 means the
+    /// contents of the buffer are undefined and it must be fully repainted.
+    pub age: u32,
+}
+
+/// A chain of SHM buffers for a surface
+///
+/// This is a set of 2 to 4 buffers used in turn to draw the contents of a surface: each
+/// time you need to draw, the swapchain hands you a buffer that is not in use by the
+/// compositor. Buffers are only created when all the existing ones are in use, so a swapchain
+/// only grows to its maximum number of buffers if the compositor holds them for long.
+///
+/// The buffers are sized from the size and scale of the surface given when requesting them,
+/// and reallocated when these change. Their age is tracked, so that you can only repaint
+/// the parts of the surface that changed since the contents of a buffer were drawn.
+///
+/// Each buffer that is handed out must be attached to the surface and committed, otherwise
+/// the compositor never releases it and the swapchain keeps believing it is in use.
+#[derive(Debug)]
+pub struct Swapchain {
+    shm: Attached<wl_shm::WlShm>,
+    format: wl_shm::Format,
+    slots: Vec<SwapSlot>,
+    state: Rc<SwapState>,
+    frame: u64,
+    size: (u32, u32, i32),
+}
+
+impl Swapchain {
+    /// Create a new swapchain
+    ///
+    /// `count` is the maximum number of buffers of the swapchain, it is clamped to the
+    /// `2..=4` range.
+    pub fn new(shm: Attached<wl_shm::WlShm>, count: usize, format: wl_shm::Format) -> Swapchain {
+        let count = match count {
+            0..=2 => 2,
+            3 => 3,
+            _ => 4,
+        };
+        Swapchain {
+            shm,
+            format,
+            slots: Vec::with_capacity(count),
+            state: Rc::new(SwapState {
+                count,
+                busy: Cell::new(0),
+                wakers: RefCell::new(Vec::new()),
+            }),
+            frame: 0,
+            size: (0, 0, 1),
+        }
+    }
+
+    /// The pixel format of the buffers
+    pub fn format(&self) -> wl_shm::Format {
+        self.format
+    }
+
+    /// Change the pixel format of the buffers
+    ///
+    /// The contents of all the buffers are invalidated.
+    pub fn set_format(&mut self, format: wl_shm::Format) {
+        if format != self.format {
+            self.format = format;
+            self.invalidate();
+        }
+    }
+
+    /// The maximum number of buffers of this swapchain
+    pub fn count(&self) -> usize {
+        self.state.count
+    }
+
+    /// Whether a buffer can be handed out right now
+    pub fn has_free_buffer(&self) -> bool {
+        self.state.busy.get() < self.state.count
+    }
+
+    /// Mark the contents of all the buffers as undefined
+    ///
+    /// The next buffers handed out have an age of 0.
+    pub fn invalidate(&mut self) {
+        for slot in &mut self.slots {
+            slot.last_frame = None;
+        }
+    }
+
+    /// Get the next buffer to draw to
+    ///
+    /// `width` and `height` are the size of the surface and `scale` its buffer scale, the
+    /// buffer has a size of `width * scale` x `height * scale` pixels. Remember to set the
+    /// buffer scale of the surface accordingly.
+    ///
+    /// Returns `Ok(None)` if all the buffers are in use by the compositor, you can then
+    /// use [`wait_for_buffer`](#method.wait_for_buffer) to know when one is released.
+    pub fn next_buffer(
+        &mut self,
+        width: u32,
+        height: u32,
+        scale: i32,
+    ) -> Result<Option<SwapchainBuffer<'_>>, CanvasError> {
+        let scale = std::cmp::max(scale, 1);
+        let (buf_width, buf_height) = (width * scale as u32, height * scale as u32);
+        check_format(&self.shm, self.format, buf_width, buf_height)?;
+        if (width, height, scale) != self.size {
+            self.size = (width, height, scale);
+            self.invalidate();
+        }
+
+        // prefer the free buffer with the most recent contents, to minimize repainting
+        let idx = self
+            .slots
+            .iter()
+            .enumerate()
+            .filter(|&(_, slot)| !slot.pool.is_used())
+            .max_by_key(|&(_, slot)| slot.last_frame.map(|f| f + 1).unwrap_or(0))
+            .map(|(i, _)| i);
+        let idx = match idx {
+            Some(idx) => idx,
+            None if self.slots.len() < self.state.count => {
+                let state = self.state.clone();
+                let mut pool = MemPool::new(self.shm.clone(), move |_| state.release())?;
+                pool.set_shrink_policy(Some(ShrinkPolicy {
+                    threshold: REALLOC_THRESHOLD,
+                    delay: Duration::from_secs(0),
+                }));
+                self.slots.push(SwapSlot { pool, last_frame: None });
+                self.slots.len() - 1
+            }
+            None => return Ok(None),
+        };
+
+        self.frame += 1;
+        let frame = self.frame;
+        let slot = &mut self.slots[idx];
+        let age = slot.last_frame.map(|f| (frame - f) as u32).unwrap_or(0);
+        slot.last_frame = Some(frame);
+        let (canvas, buffer) = slot.pool.canvas_buffer(0, buf_width, buf_height, self.format)?;
+        self.state.busy.set(self.state.busy.get() + 1);
+        Ok(Some(SwapchainBuffer { canvas, buffer, age }))
+    }
+
+    /// Wait until a buffer can be handed out
+    ///
+    /// The returned future is ready as soon as a buffer is free, it is woken up when the
+    /// compositor releases a buffer while processing the Wayland events.
+    pub fn wait_for_buffer(&self) -> BufferAvailable {
+        BufferAvailable { state: self.state.clone() }
+    }
+}
+
+/// A future that is ready when a [`Swapchain`](struct.Swapchain.html) has a free buffer
+///
+/// It is created by [`Swapchain::wait_for_buffer`](struct.Swapchain.html#method.wait_for_buffer).
+#[derive(Debug)]
+pub struct BufferAvailable {
+    state: Rc<SwapState>,
+}
+
+impl Future for BufferAvailable {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.state.busy.get() < self.state.count {
+            Poll::Ready(())
+        } else {
+            let mut wakers = self.state.wakers.borrow_mut();
+            if !wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                wakers.push(cx.waker().clone());
+            }
+            Poll::Pending
+        }
+    }
+}
+
+impl<E> crate::environment::Environment<E>
+where
+    E: crate::environment::GlobalHandler<wl_shm::WlShm>,
+{
+    /// Create a swapchain of up to `count` buffers of given format
+    ///
+    /// See [`Swapchain`](../shm/struct.Swapchain.html) for details.
+    pub fn create_swapchain(&self, count: usize, format: wl_shm::Format) -> Swapchain {
+        Swapchain::new(self.require_global(), count, format)
+    }
+}
@@ -0,0 +1,336 @@
+use std::{
+    fmt,
+    fs::File,
+    io,
+    os::unix::io::AsRawFd,
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+use memmap2::MmapRaw;
+
+use wayland_client::{
+    protocol::{wl_buffer, wl_shm, wl_shm_pool},
+    Attached, Main,
+};
+
+use super::{
+    file::{create_shm_file, shm_file_strategy},
+    format::{check_buffer, ShmFormats},
+    mempool::free_block,
+    Canvas, CanvasError,
+};
+
+// allocations are aligned on this many bytes
+const ALIGN: usize = 64;
+
+#[derive(Debug)]
+struct State {
+    file: File,
+    len: usize,
+    // detached handle, used to resize the pool from any thread
+    pool: wl_shm_pool::WlShmPool,
+    mmap: Arc<MmapRaw>,
+    free_list: Vec<(usize, usize)>,
+    busy: usize,
+    released: u64,
+}
+
+impl State {
+    fn alloc(&mut self, size: usize) -> io::Result<usize> {
+        for (offset, len) in self.free_list.iter_mut() {
+            if *len >= size {
+                let rv = *offset;
+                *len -= size;
+                *offset += size;
+                return Ok(rv);
+            }
+        }
+        let mut rv = self.len;
+        let mut pop_tail = false;
+        if let Some((start, len)) = self.free_list.last() {
+            if start + len == self.len {
+                rv -= len;
+                pop_tail = true;
+            }
+        }
+        // resize like Vec::reserve, always at least doubling
+        let target = std::cmp::max(rv + size, self.len * 2);
+        self.file.set_len(target as u64)?;
+        // the previous mapping stays alive as long as render targets use it
+        self.mmap = Arc::new(MmapRaw::map_raw(&self.file)?);
+        self.pool.resize(target as i32);
+        self.len = target;
+        if pop_tail {
+            self.free_list.pop();
+        }
+        if target > rv + size {
+            self.free_list.push((rv + size, target - rv - size));
+        }
+        Ok(rv)
+    }
+}
+
+impl Drop for State {
+    fn drop(&mut self) {
+        self.pool.destroy();
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<State>,
+    released: Condvar,
+    // the formats advertised by the compositor, `None` if unknown
+    formats: Option<Vec<wl_shm::Format>>,
+}
+
+impl Shared {
+    fn free(&self, offset: usize, len: usize) {
+        free_block(&mut self.state.lock().unwrap().free_list, offset, len);
+    }
+}
+
+/// A memory pool whose buffers can be drawn from other threads
+///
+/// The pool itself lives on the thread dispatching the Wayland events, as it creates the
+/// buffers and receives their release events. Drawing happens through a
+/// [`SyncMemPoolHandle`](struct.SyncMemPoolHandle.html), which can be sent to a render thread
+/// to allocate [`RenderTarget`](struct.RenderTarget.html)s. Once a target is drawn, send it
+/// back to the pool and [`submit`](#method.submit) it to get the buffer to attach to
+/// your surface.
+///
+/// Submitting a target consumes it, so its memory cannot be written to while the compositor
+/// uses the buffer. The memory is returned to the pool when the compositor releases the
+/// buffer.
+pub struct SyncMemPool {
+    pool: Main<wl_shm_pool::WlShmPool>,
+    shared: Arc<Shared>,
+}
+
+impl SyncMemPool {
+    /// Create a new memory pool associated with given shm
+    pub fn new(shm: Attached<wl_shm::WlShm>) -> io::Result<SyncMemPool> {
+        let len = 4096;
+        let file = create_shm_file(shm_file_strategy())?;
+        file.set_len(len as u64)?;
+        let pool = shm.create_pool(file.as_raw_fd(), len as i32);
+        let mmap = Arc::new(MmapRaw::map_raw(&file)?);
+        let formats = shm.as_ref().user_data().get::<ShmFormats>().map(|f| f.borrow().clone());
+        let state = State {
+            file,
+            len,
+            pool: pool.detach(),
+            mmap,
+            free_list: vec![(0, len)],
+            busy: 0,
+            released: 0,
+        };
+        Ok(SyncMemPool {
+            pool,
+            shared: Arc::new(Shared {
+                state: Mutex::new(state),
+                released: Condvar::new(),
+                formats,
+            }),
+        })
+    }
+
+    /// Get a handle to allocate render targets from this pool
+    ///
+    /// The handle can be sent to other threads.
+    pub fn handle(&self) -> SyncMemPoolHandle {
+        SyncMemPoolHandle { shared: self.shared.clone() }
+    }
+
+    /// Create the buffer of a render target
+    ///
+    /// The contents of the target must be fully drawn, as they can no longer be changed. The
+    /// memory of the target is returned to the pool when the compositor releases the buffer, so
+    /// the buffer must be attached to a surface and committed.
+    ///
+    /// Panics if the target was allocated from another pool.
+    pub fn submit(&self, mut target: RenderTarget) -> wl_buffer::WlBuffer {
+        assert!(Arc::ptr_eq(&self.shared, &target.shared), "Render target from another pool.");
+        target.submitted = true;
+        let (offset, len) = (target.offset, target.len);
+        let stride = target.stride as i32;
+        let buffer = self.pool.create_buffer(
+            offset as i32,
+            target.width as i32,
+            target.height as i32,
+            stride,
+            target.format,
+        );
+        self.shared.state.lock().unwrap().busy += 1;
+        let shared = self.shared.clone();
+        buffer.quick_assign(move |buffer, event, _| match event {
+            wl_buffer::Event::Release => {
+                buffer.destroy();
+                {
+                    let mut state = shared.state.lock().unwrap();
+                    free_block(&mut state.free_list, offset, len);
+                    state.busy -= 1;
+                    state.released += 1;
+                }
+                shared.released.notify_all();
+            }
+            _ => unreachable!(),
+        });
+        buffer.detach()
+    }
+}
+
+impl fmt::Debug for SyncMemPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SyncMemPool").field("pool", &self.pool).finish()
+    }
+}
+
+/// A handle to allocate render targets from a [`SyncMemPool`](struct.SyncMemPool.html)
+///
+/// This handle can be cloned and sent to other threads.
+#[derive(Debug, Clone)]
+pub struct SyncMemPoolHandle {
+    shared: Arc<Shared>,
+}
+
+impl SyncMemPoolHandle {
+    /// Allocate a render target of given size and format
+    ///
+    /// The pool is grown if needed. The format is checked against the list of formats
+    /// advertised by the compositor when the pool was created, and an empty size is
+    /// rejected with `CanvasError::InvalidSize`.
+    pub fn render_target(
+        &self,
+        width: u32,
+        height: u32,
+        format: wl_shm::Format,
+    ) -> Result<RenderTarget, CanvasError> {
+        let stride = check_buffer(self.shared.formats.as_deref(), format, width, height)?;
+        let len = (height as usize * stride as usize + ALIGN - 1) & !(ALIGN - 1);
+        let mut state = self.shared.state.lock().unwrap();
+        let offset = state.alloc(len)?;
+        Ok(RenderTarget {
+            shared: self.shared.clone(),
+            mmap: state.mmap.clone(),
+            offset,
+            len,
+            width,
+            height,
+            stride: stride as usize,
+            format,
+            submitted: false,
+        })
+    }
+
+    /// The number of buffers of this pool currently held by the compositor
+    pub fn busy_buffers(&self) -> usize {
+        self.shared.state.lock().unwrap().busy
+    }
+
+    /// Wait until the compositor releases a buffer of this pool
+    ///
+    /// Returns immediately if the compositor holds no buffer. Otherwise, blocks until a buffer
+    /// is released or the timeout expires, in which case `false` is returned. Release events are
+    /// received by the thread dispatching the Wayland events, so this must not be called
+    /// from that thread.
+    pub fn wait_for_release(&self, timeout: Option<Duration>) -> bool {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        let mut state = self.shared.state.lock().unwrap();
+        if state.busy == 0 {
+            return true;
+        }
+        let released = state.released;
+        while state.released == released {
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return false;
+                    }
+                    self.shared.released.wait_timeout(state, deadline - now).unwrap().0
+                }
+                None => self.shared.released.wait(state).unwrap(),
+            };
+        }
+        true
+    }
+}
+
+/// A block of memory of a [`SyncMemPool`](struct.SyncMemPool.html) to draw a buffer into
+///
+/// Render targets are allocated with a [`SyncMemPoolHandle`](struct.SyncMemPoolHandle.html),
+/// and can be sent between threads. Dropping a target without submitting it returns its
+/// memory to the pool.
+pub struct RenderTarget {
+    shared: Arc<Shared>,
+    mmap: Arc<MmapRaw>,
+    offset: usize,
+    len: usize,
+    width: u32,
+    height: u32,
+    stride: usize,
+    format: wl_shm::Format,
+    submitted: bool,
+}
+
+impl RenderTarget {
+    /// The width of the target, in pixels
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height of the target, in pixels
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The pixel format of the target
+    pub fn format(&self) -> wl_shm::Format {
+        self.format
+    }
+
+    /// A typed view on the contents of the target
+    pub fn canvas(&mut self) -> Canvas<'_> {
+        // the allocator gives each target its own range of the pool, and the target is
+        // borrowed mutably for as long as the slice lives
+        let data = unsafe {
+            std::slice::from_raw_parts_mut(self.mmap.as_mut_ptr().add(self.offset), self.len)
+        };
+        Canvas::new(data, self.width, self.height, self.stride as i32, self.format)
+    }
+}
+
+impl fmt::Debug for RenderTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RenderTarget")
+            .field("offset", &self.offset)
+            .field("len", &self.len)
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("stride", &self.stride)
+            .field("format", &self.format)
+            .finish()
+    }
+}
+
+impl Drop for RenderTarget {
+    fn drop(&mut self) {
+        if !self.submitted {
+            self.shared.free(self.offset, self.len);
+        }
+    }
+}
+
+impl<E> crate::environment::Environment<E>
+where
+    E: crate::environment::GlobalHandler<wl_shm::WlShm>,
+{
+    /// Create a memory pool whose buffers can be drawn from other threads
+    ///
+    /// See [`SyncMemPool`](../shm/struct.SyncMemPool.html) for details.
+    pub fn create_sync_pool(&self) -> io::Result<SyncMemPool> {
+        SyncMemPool::new(self.require_global::<wl_shm::WlShm>())
+    }
+}
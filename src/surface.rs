@@ -7,31 +7,85 @@ use wayland_client::{
 
 use crate::output::{add_output_listener, with_output_info, OutputListener};
 
+type ScaleCallback = dyn FnMut(i32, wl_surface::WlSurface, DispatchData);
+type TransformCallback = dyn FnMut(wl_output::Transform, wl_surface::WlSurface, DispatchData);
+
+#[derive(Clone, Default)]
+pub(crate) struct SurfaceCallbacks {
+    scale: Option<Rc<RefCell<ScaleCallback>>>,
+    transform: Option<Rc<RefCell<TransformCallback>>>,
+}
+
+impl SurfaceCallbacks {
+    fn new<S, T>(scale: Option<S>, transform: Option<T>) -> SurfaceCallbacks
+    where
+        S: FnMut(i32, wl_surface::WlSurface, DispatchData) + 'static,
+        T: FnMut(wl_output::Transform, wl_surface::WlSurface, DispatchData) + 'static,
+    {
+        SurfaceCallbacks {
+            scale: scale.map(|c| Rc::new(RefCell::new(c)) as Rc<RefCell<ScaleCallback>>),
+            transform: transform
+                .map(|c| Rc::new(RefCell::new(c)) as Rc<RefCell<TransformCallback>>),
+        }
+    }
+
+    fn notify(
+        &self,
+        old: (i32, wl_output::Transform),
+        new: (i32, wl_output::Transform),
+        surface: wl_surface::WlSurface,
+        mut ddata: DispatchData,
+    ) {
+        if let Some(ref cb) = self.scale {
+            if old.0 != new.0 {
+                (&mut *cb.borrow_mut())(new.0, surface.clone(), ddata.reborrow());
+            }
+        }
+        if let Some(ref cb) = self.transform {
+            if old.1 != new.1 {
+                (&mut *cb.borrow_mut())(new.1, surface, ddata);
+            }
+        }
+    }
+}
+
+struct SurfaceOutput {
+    output: wl_output::WlOutput,
+    scale_factor: i32,
+    transform: wl_output::Transform,
+    _listener: OutputListener,
+}
+
 pub(crate) struct SurfaceUserData {
     scale_factor: i32,
-    outputs: Vec<(wl_output::WlOutput, i32, OutputListener)>,
+    transform: wl_output::Transform,
+    // in the order the surface entered them, the first one is the primary output
+    outputs: Vec<SurfaceOutput>,
 }
 
 impl SurfaceUserData {
     fn new() -> Self {
-        SurfaceUserData { scale_factor: 1, outputs: Vec::new() }
+        SurfaceUserData {
+            scale_factor: 1,
+            transform: wl_output::Transform::Normal,
+            outputs: Vec::new(),
+        }
     }
 
-    pub(crate) fn enter<F>(
+    pub(crate) fn enter(
         &mut self,
         output: wl_output::WlOutput,
         surface: wl_surface::WlSurface,
-        callback: &Option<Rc<RefCell<F>>>,
-    ) where
-        F: FnMut(i32, wl_surface::WlSurface, DispatchData) + 'static,
-    {
-        let output_scale = with_output_info(&output, |info| info.scale_factor).unwrap_or(1);
+        callbacks: &SurfaceCallbacks,
+    ) {
+        let (output_scale, output_transform) =
+            with_output_info(&output, |info| (info.scale_factor, info.transform))
+                .unwrap_or((1, wl_output::Transform::Normal));
         let my_surface = surface.clone();
-        // Use a UserData to safely share the callback with the other thread
-        let my_callback = wayland_client::UserData::new();
-        if let Some(ref cb) = callback {
-            my_callback.set(|| cb.clone());
-        }
+        // Use a UserData to safely share the callbacks with the other thread
+        let my_callbacks = wayland_client::UserData::new();
+        let callbacks = callbacks.clone();
+        my_callbacks.set(move || callbacks);
         let listener = add_output_listener(&output, move |output, info, ddata| {
             let mut user_data = my_surface
                 .as_ref()
@@ -40,53 +94,57 @@ impl SurfaceUserData {
                 .unwrap()
                 .lock()
                 .unwrap();
-            // update the scale factor of the relevant output
-            for (ref o, ref mut factor, _) in user_data.outputs.iter_mut() {
-                if o.as_ref().equals(output.as_ref()) {
+            // update the scale factor and transform of the relevant output
+            for o in user_data.outputs.iter_mut() {
+                if o.output.as_ref().equals(output.as_ref()) {
                     if info.obsolete {
                         // an output that no longer exists is marked by a scale factor of -1
-                        *factor = -1;
+                        o.scale_factor = -1;
                     } else {
-                        *factor = info.scale_factor;
+                        o.scale_factor = info.scale_factor;
+                        o.transform = info.transform;
                     }
                     break;
                 }
             }
-            // recompute the scale factor with the new info
-            let callback = my_callback.get::<Rc<RefCell<F>>>().cloned();
-            let old_scale_factor = user_data.scale_factor;
-            let new_scale_factor = user_data.recompute_scale_factor();
+            // recompute the scale factor and transform with the new info
+            let callbacks = my_callbacks.get::<SurfaceCallbacks>().cloned();
+            let old = (user_data.scale_factor, user_data.transform);
+            let new = user_data.recompute();
             drop(user_data);
-            if let Some(ref cb) = callback {
-                if old_scale_factor != new_scale_factor {
-                    (&mut *cb.borrow_mut())(new_scale_factor, surface.clone(), ddata);
-                }
+            if let Some(callbacks) = callbacks {
+                callbacks.notify(old, new, surface.clone(), ddata);
             }
         });
-        self.outputs.push((output, output_scale, listener));
+        self.outputs.push(SurfaceOutput {
+            output,
+            scale_factor: output_scale,
+            transform: output_transform,
+            _listener: listener,
+        });
     }
 
     pub(crate) fn leave(&mut self, output: &wl_output::WlOutput) {
-        self.outputs.retain(|(ref output2, _, _)| !output.as_ref().equals(output2.as_ref()));
+        self.outputs.retain(|o| !output.as_ref().equals(o.output.as_ref()));
     }
 
-    fn recompute_scale_factor(&mut self) -> i32 {
+    fn recompute(&mut self) -> (i32, wl_output::Transform) {
         let mut new_scale_factor = 1;
-        self.outputs.retain(|&(_, output_scale, _)| {
-            if output_scale > 0 {
-                new_scale_factor = ::std::cmp::max(new_scale_factor, output_scale);
+        self.outputs.retain(|o| {
+            if o.scale_factor > 0 {
+                new_scale_factor = ::std::cmp::max(new_scale_factor, o.scale_factor);
                 true
             } else {
                 // cleanup obsolete output
                 false
             }
         });
-        if self.outputs.is_empty() {
-            // don't update the scale factor if we are not displayed on any output
-            return self.scale_factor;
+        if let Some(primary) = self.outputs.first() {
+            // don't update if we are not displayed on any output
+            self.scale_factor = new_scale_factor;
+            self.transform = primary.transform;
         }
-        self.scale_factor = new_scale_factor;
-        new_scale_factor
+        (self.scale_factor, self.transform)
     }
 }
 
@@ -97,28 +155,31 @@ pub(crate) fn setup_surface<F>(
 where
     F: FnMut(i32, wl_surface::WlSurface, DispatchData) + 'static,
 {
-    let callback = callback.map(|c| Rc::new(RefCell::new(c)));
+    let callbacks = SurfaceCallbacks::new(callback, None::<fn(_, _, DispatchData)>);
+    setup_surface_with_callbacks(surface, callbacks)
+}
+
+pub(crate) fn setup_surface_with_callbacks(
+    surface: Main<wl_surface::WlSurface>,
+    callbacks: SurfaceCallbacks,
+) -> Attached<wl_surface::WlSurface> {
     surface.quick_assign(move |surface, event, ddata| {
         let mut user_data =
             surface.as_ref().user_data().get::<Mutex<SurfaceUserData>>().unwrap().lock().unwrap();
         match event {
             wl_surface::Event::Enter { output } => {
-                // Passing the callback to be added to output listener
-                user_data.enter(output, surface.detach(), &callback);
+                // Passing the callbacks to be added to output listener
+                user_data.enter(output, surface.detach(), &callbacks);
             }
             wl_surface::Event::Leave { output } => {
                 user_data.leave(&output);
             }
             _ => unreachable!(),
         };
-        let old_scale_factor = user_data.scale_factor;
-        let new_scale_factor = user_data.recompute_scale_factor();
+        let old = (user_data.scale_factor, user_data.transform);
+        let new = user_data.recompute();
         drop(user_data);
-        if let Some(ref cb) = callback {
-            if old_scale_factor != new_scale_factor {
-                (&mut *cb.borrow_mut())(new_scale_factor, surface.detach(), ddata);
-            }
-        }
+        callbacks.notify(old, new, surface.detach(), ddata);
     });
     surface.as_ref().user_data().set_threadsafe(|| Mutex::new(SurfaceUserData::new()));
     surface.into()
@@ -149,8 +210,47 @@ impl<E: crate::environment::GlobalHandler<wl_compositor::WlCompositor>>
         &self,
         f: F,
     ) -> Attached<wl_surface::WlSurface> {
+        self.create_surface_with_callbacks(Some(f), None::<fn(_, _, DispatchData)>)
+    }
+
+    /// Create a DPI-aware surface with a callback for its buffer transform
+    ///
+    /// This method is like `create_surface`, but the provided callback will be notified
+    /// whenever the transform of the primary output of the surface changes. The primary output
+    /// is the first output the surface entered among the ones it is currently displayed on.
+    ///
+    /// Drawing your buffers with this transform and setting it with
+    /// `wl_surface.set_buffer_transform` lets the compositor display them without rotating them.
+    /// See [`BufferMapping`](../struct.BufferMapping.html) to map coordinates between the surface
+    /// and such buffers.
+    pub fn create_surface_with_transform_callback<
+        F: FnMut(wl_output::Transform, wl_surface::WlSurface, DispatchData) + 'static,
+    >(
+        &self,
+        f: F,
+    ) -> Attached<wl_surface::WlSurface> {
+        self.create_surface_with_callbacks(None::<fn(_, _, DispatchData)>, Some(f))
+    }
+
+    /// Create a DPI-aware surface with callbacks for its scale factor and buffer transform
+    ///
+    /// This combines [`create_surface_with_scale_callback`](#method.create_surface_with_scale_callback)
+    /// and [`create_surface_with_transform_callback`](#method.create_surface_with_transform_callback),
+    /// each callback being optional.
+    pub fn create_surface_with_callbacks<S, T>(
+        &self,
+        scale: Option<S>,
+        transform: Option<T>,
+    ) -> Attached<wl_surface::WlSurface>
+    where
+        S: FnMut(i32, wl_surface::WlSurface, DispatchData) + 'static,
+        T: FnMut(wl_output::Transform, wl_surface::WlSurface, DispatchData) + 'static,
+    {
         let compositor = self.require_global::<wl_compositor::WlCompositor>();
-        setup_surface(compositor.create_surface(), Some(f))
+        setup_surface_with_callbacks(
+            compositor.create_surface(),
+            SurfaceCallbacks::new(scale, transform),
+        )
     }
 }
 
@@ -169,6 +269,24 @@ pub fn get_surface_scale_factor(surface: &wl_surface::WlSurface) -> i32 {
         .scale_factor
 }
 
+/// Returns the transform of the primary output of a surface.
+///
+/// The primary output is the first output the surface entered among the ones it is
+/// currently displayed on.
+///
+/// Panics if the surface was not created using `Environment::create_surface` or
+/// `Environment::create_surface_with_dpi_callback`.
+pub fn get_surface_transform(surface: &wl_surface::WlSurface) -> wl_output::Transform {
+    surface
+        .as_ref()
+        .user_data()
+        .get::<Mutex<SurfaceUserData>>()
+        .expect("SCTK: Surface was not created by SCTK.")
+        .lock()
+        .unwrap()
+        .transform
+}
+
 /// Returns a list of outputs the surface is displayed on.
 ///
 /// Panics if the surface was not created using `Environment::create_surface` or
@@ -183,6 +301,551 @@ pub fn get_surface_outputs(surface: &wl_surface::WlSurface) -> Vec<wl_output::Wl
         .unwrap()
         .outputs
         .iter()
-        .map(|(ref output, _, _)| output.clone())
+        .map(|o| o.output.clone())
         .collect()
 }
+
+// the extent used to damage a whole surface, `i32::MAX`
+const FULL_EXTENT: i32 = 0x7FFF_FFFF;
+
+// default number of rectangles kept by a `Damage` before merging them
+const DEFAULT_MAX_RECTS: usize = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Rect {
+    x1: i32,
+    y1: i32,
+    x2: i32,
+    y2: i32,
+}
+
+impl Rect {
+    fn new(x: i32, y: i32, width: i32, height: i32) -> Rect {
+        Rect { x1: x, y1: y, x2: x.saturating_add(width), y2: y.saturating_add(height) }
+    }
+
+    fn from_tuple((x, y, width, height): (i32, i32, i32, i32)) -> Rect {
+        Rect::new(x, y, width, height)
+    }
+
+    fn contains(&self, other: &Rect) -> bool {
+        self.x1 <= other.x1 && self.y1 <= other.y1 && self.x2 >= other.x2 && self.y2 >= other.y2
+    }
+
+    fn union(&self, other: &Rect) -> Rect {
+        Rect {
+            x1: self.x1.min(other.x1),
+            y1: self.y1.min(other.y1),
+            x2: self.x2.max(other.x2),
+            y2: self.y2.max(other.y2),
+        }
+    }
+
+    fn area(&self) -> i64 {
+        (self.x2 as i64 - self.x1 as i64) * (self.y2 as i64 - self.y1 as i64)
+    }
+
+    fn scaled_up(&self, scale: i32) -> Rect {
+        Rect {
+            x1: self.x1.saturating_mul(scale),
+            y1: self.y1.saturating_mul(scale),
+            x2: self.x2.saturating_mul(scale),
+            y2: self.y2.saturating_mul(scale),
+        }
+    }
+
+    fn scaled_down(&self, scale: i32) -> Rect {
+        // round outwards so that the damage covers all the affected pixels
+        let ceil = |v: i32| -(-v).div_euclid(scale);
+        Rect {
+            x1: self.x1.div_euclid(scale),
+            y1: self.y1.div_euclid(scale),
+            x2: ceil(self.x2),
+            y2: ceil(self.y2),
+        }
+    }
+}
+
+// Add a rectangle to a list, keeping at most `max` rectangles
+fn push_rect(rects: &mut Vec<Rect>, rect: Rect, max: usize) {
+    if rects.iter().any(|r| r.contains(&rect)) {
+        return;
+    }
+    rects.retain(|r| !rect.contains(r));
+    if rects.len() < max {
+        rects.push(rect);
+        return;
+    }
+    // merge the new rectangle with the one whose area grows the least
+    let (idx, merged) = rects
+        .iter()
+        .map(|r| r.union(&rect))
+        .enumerate()
+        .min_by_key(|&(i, ref u)| u.area() - rects[i].area())
+        .unwrap();
+    rects.swap_remove(idx);
+    push_rect(rects, merged, max);
+}
+
+// Merge the damage in buffer and surface coordinates into the coordinate space it is sent in
+fn merge_damage(
+    mapping: &BufferMapping,
+    buffer: Vec<Rect>,
+    surface: Vec<Rect>,
+    use_buffer: bool,
+    max: usize,
+) -> Vec<Rect> {
+    let (mut rects, other) = if use_buffer { (buffer, surface) } else { (surface, buffer) };
+    let scale = std::cmp::max(mapping.scale, 1);
+    for rect in other {
+        let rect = match (mapping.transform, use_buffer) {
+            // exact integer math for untransformed buffers
+            (wl_output::Transform::Normal, true) => rect.scaled_up(scale),
+            (wl_output::Transform::Normal, false) => rect.scaled_down(scale),
+            (_, true) => Rect::from_tuple(mapping.surface_rect_to_buffer(
+                rect.x1,
+                rect.y1,
+                rect.x2 - rect.x1,
+                rect.y2 - rect.y1,
+            )),
+            (_, false) => Rect::from_tuple(mapping.buffer_rect_to_surface(
+                rect.x1,
+                rect.y1,
+                rect.x2 - rect.x1,
+                rect.y2 - rect.y1,
+            )),
+        };
+        push_rect(&mut rects, rect, max);
+    }
+    rects
+}
+
+/// An accumulator of the damage of a surface
+///
+/// Rather than damaging a whole surface each time its contents change, you can
+/// register the parts that changed in a `Damage`, in buffer or surface coordinates,
+/// and send them all just before committing the surface. The rectangles are merged
+/// so that their number stays bounded.
+///
+/// When committing, the damage is sent with `wl_surface.damage_buffer` if the
+/// `wl_compositor` was bound with version 4 or higher, and with `wl_surface.damage`
+/// otherwise, converting the rectangles as needed using the buffer scale given to
+/// [`set_buffer_scale`](#method.set_buffer_scale), or the buffer scale and transform given to
+/// [`set_buffer_mapping`](#method.set_buffer_mapping).
+#[derive(Debug)]
+pub struct Damage {
+    surface: wl_surface::WlSurface,
+    mapping: BufferMapping,
+    max_rects: usize,
+    full: bool,
+    buffer: Vec<Rect>,
+    surface_rects: Vec<Rect>,
+}
+
+impl Damage {
+    /// Create a damage accumulator for a surface
+    ///
+    /// It assumes a buffer scale of 1, and initially damages the whole surface.
+    pub fn new(surface: &wl_surface::WlSurface) -> Damage {
+        Damage {
+            surface: surface.clone(),
+            mapping: BufferMapping::new(wl_output::Transform::Normal, 1, 0, 0),
+            max_rects: DEFAULT_MAX_RECTS,
+            full: true,
+            buffer: Vec::new(),
+            surface_rects: Vec::new(),
+        }
+    }
+
+    /// Set the maximum number of rectangles kept for each coordinate space
+    ///
+    /// Past this number, rectangles are merged together. The default is 16.
+    pub fn set_max_rects(&mut self, max_rects: usize) {
+        self.max_rects = std::cmp::max(max_rects, 1);
+    }
+
+    /// Set the buffer scale of the surface
+    ///
+    /// This calls `wl_surface.set_buffer_scale`. If the scale changed, the whole surface
+    /// is damaged.
+    pub fn set_buffer_scale(&mut self, scale: i32) {
+        if std::cmp::max(scale, 1) != self.mapping.scale {
+            self.surface.set_buffer_scale(scale);
+            self.mapping.scale = std::cmp::max(scale, 1);
+            self.full = true;
+        }
+    }
+
+    /// Set the buffer scale and transform of the surface, along with its size
+    ///
+    /// This calls `wl_surface.set_buffer_scale` and `wl_surface.set_buffer_transform`. The
+    /// damage in surface coordinates is then mapped to the transformed buffer when it is sent.
+    /// If the scale or the transform changed, the whole surface is damaged.
+    pub fn set_buffer_mapping(&mut self, mapping: BufferMapping) {
+        self.set_buffer_scale(mapping.scale);
+        if mapping.transform != self.mapping.transform {
+            if self.surface.as_ref().version() >= 2 {
+                self.surface.set_buffer_transform(mapping.transform);
+            }
+            self.full = true;
+        }
+        self.mapping = mapping;
+    }
+
+    /// Damage a rectangle, in buffer coordinates
+    pub fn add_buffer(&mut self, x: i32, y: i32, width: i32, height: i32) {
+        if !self.full && width > 0 && height > 0 {
+            push_rect(&mut self.buffer, Rect::new(x, y, width, height), self.max_rects);
+        }
+    }
+
+    /// Damage a rectangle, in surface coordinates
+    pub fn add_surface(&mut self, x: i32, y: i32, width: i32, height: i32) {
+        if !self.full && width > 0 && height > 0 {
+            push_rect(&mut self.surface_rects, Rect::new(x, y, width, height), self.max_rects);
+        }
+    }
+
+    /// Damage the whole surface
+    pub fn add_all(&mut self) {
+        self.full = true;
+        self.buffer.clear();
+        self.surface_rects.clear();
+    }
+
+    /// Whether no damage was accumulated since the last commit
+    pub fn is_empty(&self) -> bool {
+        !self.full && self.buffer.is_empty() && self.surface_rects.is_empty()
+    }
+
+    /// Send the accumulated damage to the surface, without committing it
+    ///
+    /// The accumulated damage is cleared.
+    pub fn flush(&mut self) {
+        let use_buffer = self.surface.as_ref().version() >= 4;
+        let rects = if self.full {
+            self.buffer.clear();
+            self.surface_rects.clear();
+            vec![Rect::new(0, 0, FULL_EXTENT, FULL_EXTENT)]
+        } else {
+            merge_damage(
+                &self.mapping,
+                std::mem::take(&mut self.buffer),
+                std::mem::take(&mut self.surface_rects),
+                use_buffer,
+                self.max_rects,
+            )
+        };
+        for rect in rects {
+            let (width, height) = (rect.x2 - rect.x1, rect.y2 - rect.y1);
+            if use_buffer {
+                self.surface.damage_buffer(rect.x1, rect.y1, width, height);
+            } else {
+                self.surface.damage(rect.x1, rect.y1, width, height);
+            }
+        }
+        self.full = false;
+    }
+
+    /// Send the accumulated damage to the surface and commit it
+    ///
+    /// The accumulated damage is cleared.
+    pub fn commit(&mut self) {
+        self.flush();
+        self.surface.commit();
+    }
+}
+
+/// A mapping between surface coordinates and the coordinates of a transformed buffer
+///
+/// When a buffer is attached with a buffer transform and scale (see
+/// [`get_surface_transform`](fn.get_surface_transform.html)), its contents are rotated,
+/// flipped and scaled relative to the surface. This maps points, such as input events, and
+/// rectangles, such as damage, between both coordinate spaces.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BufferMapping {
+    /// The buffer transform
+    pub transform: wl_output::Transform,
+    /// The buffer scale
+    pub scale: i32,
+    /// The width of the surface, in surface coordinates
+    pub width: i32,
+    /// The height of the surface, in surface coordinates
+    pub height: i32,
+}
+
+impl BufferMapping {
+    /// Create a mapping for a surface of given size, in surface coordinates
+    pub fn new(
+        transform: wl_output::Transform,
+        scale: i32,
+        width: i32,
+        height: i32,
+    ) -> BufferMapping {
+        BufferMapping { transform, scale: std::cmp::max(scale, 1), width, height }
+    }
+
+    // whether the transform swaps the axes
+    fn swaps_axes(&self) -> bool {
+        use wl_output::Transform as T;
+        matches!(self.transform, T::_90 | T::_270 | T::Flipped90 | T::Flipped270)
+    }
+
+    /// The size the buffer must have, in buffer pixels
+    pub fn buffer_size(&self) -> (i32, i32) {
+        let (w, h) = (self.width * self.scale, self.height * self.scale);
+        if self.swaps_axes() {
+            (h, w)
+        } else {
+            (w, h)
+        }
+    }
+
+    /// Map a point from surface coordinates to buffer coordinates
+    pub fn surface_to_buffer(&self, x: f64, y: f64) -> (f64, f64) {
+        use wl_output::Transform as T;
+        let (w, h) = (self.width as f64, self.height as f64);
+        let (bx, by) = match self.transform {
+            T::Flipped => (w - x, y),
+            T::_90 => (y, w - x),
+            T::Flipped90 => (y, x),
+            T::_180 => (w - x, h - y),
+            T::Flipped180 => (x, h - y),
+            T::_270 => (h - y, x),
+            T::Flipped270 => (h - y, w - x),
+            _ => (x, y),
+        };
+        (bx * self.scale as f64, by * self.scale as f64)
+    }
+
+    /// Map a point from buffer coordinates to surface coordinates
+    pub fn buffer_to_surface(&self, x: f64, y: f64) -> (f64, f64) {
+        use wl_output::Transform as T;
+        let (w, h) = (self.width as f64, self.height as f64);
+        let (x, y) = (x / self.scale as f64, y / self.scale as f64);
+        match self.transform {
+            T::Flipped => (w - x, y),
+            T::_90 => (w - y, x),
+            T::Flipped90 => (y, x),
+            T::_180 => (w - x, h - y),
+            T::Flipped180 => (x, h - y),
+            T::_270 => (y, h - x),
+            T::Flipped270 => (w - y, h - x),
+            _ => (x, y),
+        }
+    }
+
+    /// Map a `(x, y, width, height)` rectangle from surface coordinates to buffer coordinates
+    pub fn surface_rect_to_buffer(
+        &self,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+    ) -> (i32, i32, i32, i32) {
+        let (x1, y1) = self.surface_to_buffer(x as f64, y as f64);
+        let (x2, y2) = self.surface_to_buffer((x + width) as f64, (y + height) as f64);
+        bounding_rect(x1, y1, x2, y2)
+    }
+
+    /// Map a `(x, y, width, height)` rectangle from buffer coordinates to surface coordinates
+    ///
+    /// The resulting rectangle is rounded outwards to cover all the affected surface area.
+    pub fn buffer_rect_to_surface(
+        &self,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+    ) -> (i32, i32, i32, i32) {
+        let (x1, y1) = self.buffer_to_surface(x as f64, y as f64);
+        let (x2, y2) = self.buffer_to_surface((x + width) as f64, (y + height) as f64);
+        bounding_rect(x1, y1, x2, y2)
+    }
+}
+
+// The smallest integer rectangle containing two corners
+fn bounding_rect(x1: f64, y1: f64, x2: f64, y2: f64) -> (i32, i32, i32, i32) {
+    let (left, top) = (x1.min(x2).floor() as i32, y1.min(y2).floor() as i32);
+    let (right, bottom) = (x1.max(x2).ceil() as i32, y1.max(y2).ceil() as i32);
+    (left, top, right - left, bottom - top)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wl_output::Transform as T;
+
+    const TRANSFORMS: [wl_output::Transform; 8] = [
+        T::Normal,
+        T::_90,
+        T::_180,
+        T::_270,
+        T::Flipped,
+        T::Flipped90,
+        T::Flipped180,
+        T::Flipped270,
+    ];
+
+    #[test]
+    fn point_round_trip() {
+        for &transform in &TRANSFORMS {
+            for &scale in &[1, 2, 3] {
+                let mapping = BufferMapping::new(transform, scale, 100, 50);
+                let (bw, bh) = mapping.buffer_size();
+                for &(x, y) in &[(0.0, 0.0), (100.0, 50.0), (12.5, 7.0), (99.0, 1.0)] {
+                    let (bx, by) = mapping.surface_to_buffer(x, y);
+                    assert!(bx >= 0.0 && bx <= bw as f64, "{:?} {} {}", transform, x, bx);
+                    assert!(by >= 0.0 && by <= bh as f64, "{:?} {} {}", transform, y, by);
+                    assert_eq!(mapping.buffer_to_surface(bx, by), (x, y), "{:?}", transform);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn buffer_size() {
+        assert_eq!(BufferMapping::new(T::Normal, 2, 100, 50).buffer_size(), (200, 100));
+        assert_eq!(BufferMapping::new(T::Flipped180, 1, 100, 50).buffer_size(), (100, 50));
+        assert_eq!(BufferMapping::new(T::_90, 2, 100, 50).buffer_size(), (100, 200));
+        assert_eq!(BufferMapping::new(T::Flipped270, 1, 100, 50).buffer_size(), (50, 100));
+        // the scale is at least 1
+        assert_eq!(BufferMapping::new(T::Normal, 0, 100, 50).buffer_size(), (100, 50));
+    }
+
+    #[test]
+    fn transformed_corners() {
+        // where the top left corner of the surface ends up in the buffer
+        let expected = [
+            (T::Normal, (0.0, 0.0)),
+            (T::_90, (0.0, 100.0)),
+            (T::_180, (100.0, 50.0)),
+            (T::_270, (50.0, 0.0)),
+            (T::Flipped, (100.0, 0.0)),
+            (T::Flipped90, (0.0, 0.0)),
+            (T::Flipped180, (0.0, 50.0)),
+            (T::Flipped270, (50.0, 100.0)),
+        ];
+        for &(transform, corner) in &expected {
+            let mapping = BufferMapping::new(transform, 1, 100, 50);
+            assert_eq!(mapping.surface_to_buffer(0.0, 0.0), corner, "{:?}", transform);
+        }
+    }
+
+    #[test]
+    fn rect_round_trip() {
+        for &transform in &TRANSFORMS {
+            let mapping = BufferMapping::new(transform, 2, 100, 50);
+            let (bw, bh) = mapping.buffer_size();
+            assert_eq!(mapping.surface_rect_to_buffer(0, 0, 100, 50), (0, 0, bw, bh));
+            let buffer = mapping.surface_rect_to_buffer(10, 5, 30, 20);
+            let (_, _, w, h) = buffer;
+            if mapping.swaps_axes() {
+                assert_eq!((w, h), (40, 60), "{:?}", transform);
+            } else {
+                assert_eq!((w, h), (60, 40), "{:?}", transform);
+            }
+            assert_eq!(
+                mapping.buffer_rect_to_surface(buffer.0, buffer.1, buffer.2, buffer.3),
+                (10, 5, 30, 20),
+                "{:?}",
+                transform
+            );
+        }
+    }
+
+    #[test]
+    fn buffer_rect_rounds_outwards() {
+        let mapping = BufferMapping::new(T::Normal, 2, 100, 50);
+        assert_eq!(mapping.buffer_rect_to_surface(1, 1, 1, 1), (0, 0, 1, 1));
+        assert_eq!(mapping.buffer_rect_to_surface(1, 3, 2, 2), (0, 1, 2, 2));
+        let mapping = BufferMapping::new(T::_180, 2, 100, 50);
+        assert_eq!(mapping.buffer_rect_to_surface(0, 0, 1, 1), (99, 49, 1, 1));
+    }
+
+    #[test]
+    fn scaled_down() {
+        assert_eq!(Rect::new(0, 0, 4, 4).scaled_down(2), Rect::new(0, 0, 2, 2));
+        assert_eq!(Rect::new(1, 1, 2, 2).scaled_down(2), Rect::new(0, 0, 2, 2));
+        assert_eq!(Rect::new(3, 5, 1, 1).scaled_down(2), Rect::new(1, 2, 1, 1));
+        assert_eq!(Rect::new(-3, -1, 2, 2).scaled_down(2), Rect::new(-2, -1, 2, 2));
+        assert_eq!(Rect::new(5, 5, 1, 1).scaled_down(3), Rect::new(1, 1, 1, 1));
+        assert_eq!(Rect::new(2, 2, 3, 3).scaled_up(2).scaled_down(2), Rect::new(2, 2, 3, 3));
+    }
+
+    #[test]
+    fn push_rect_containment() {
+        let mut rects = Vec::new();
+        push_rect(&mut rects, Rect::new(0, 0, 10, 10), 4);
+        // contained in an existing rectangle
+        push_rect(&mut rects, Rect::new(2, 2, 3, 3), 4);
+        assert_eq!(rects, vec![Rect::new(0, 0, 10, 10)]);
+        push_rect(&mut rects, Rect::new(20, 20, 5, 5), 4);
+        assert_eq!(rects.len(), 2);
+        // containing existing rectangles
+        push_rect(&mut rects, Rect::new(-1, -1, 30, 30), 4);
+        assert_eq!(rects, vec![Rect::new(-1, -1, 30, 30)]);
+    }
+
+    #[test]
+    fn push_rect_merges() {
+        let mut rects = Vec::new();
+        push_rect(&mut rects, Rect::new(0, 0, 10, 10), 2);
+        push_rect(&mut rects, Rect::new(100, 100, 10, 10), 2);
+        // merged with the closest rectangle
+        push_rect(&mut rects, Rect::new(12, 0, 10, 10), 2);
+        assert_eq!(rects.len(), 2);
+        assert!(rects.contains(&Rect::new(0, 0, 22, 10)));
+        assert!(rects.contains(&Rect::new(100, 100, 10, 10)));
+    }
+
+    #[test]
+    fn push_rect_bounded() {
+        for &max in &[1, 3, 16] {
+            let mut rects = Vec::new();
+            let added: Vec<Rect> = (0..50)
+                .map(|i| Rect::new((i * 37) % 200, (i * 53) % 150, 5 + i % 7, 3 + i % 5))
+                .collect();
+            for &rect in &added {
+                push_rect(&mut rects, rect, max);
+                assert!(rects.len() <= max);
+            }
+            // the damage still covers everything that was added
+            for rect in &added {
+                assert!(rects.iter().any(|r| r.contains(rect)), "{:?} not covered", rect);
+            }
+        }
+    }
+
+    #[test]
+    fn damage_rotated_buffer() {
+        // a 100x50 surface, with a 100x200 buffer rotated by 90 degrees
+        let mapping = BufferMapping::new(T::_90, 2, 100, 50);
+        let surface = vec![Rect::new(0, 0, 10, 5)];
+        let buffer = vec![Rect::new(0, 0, 4, 4)];
+        // the top left corner of the surface is the bottom left corner of the buffer
+        assert_eq!(
+            merge_damage(&mapping, buffer.clone(), surface.clone(), true, 16),
+            vec![Rect::new(0, 0, 4, 4), Rect::new(0, 180, 10, 20)]
+        );
+        assert_eq!(
+            merge_damage(&mapping, buffer, surface, false, 16),
+            vec![Rect::new(0, 0, 10, 5), Rect::new(98, 0, 2, 2)]
+        );
+    }
+
+    #[test]
+    fn damage_untransformed_buffer() {
+        let mapping = BufferMapping::new(T::Normal, 2, 0, 0);
+        let surface = vec![Rect::new(1, 1, 10, 5)];
+        let buffer = vec![Rect::new(3, 3, 1, 1)];
+        assert_eq!(
+            merge_damage(&mapping, buffer.clone(), surface.clone(), true, 16),
+            vec![Rect::new(2, 2, 20, 10)]
+        );
+        assert_eq!(
+            merge_damage(&mapping, buffer, surface, false, 16),
+            vec![Rect::new(1, 1, 10, 5)]
+        );
+    }
+}
@@ -6,12 +6,13 @@ use wayland_client::protocol::{
     wl_compositor, wl_pointer, wl_seat, wl_shm, wl_subcompositor, wl_subsurface, wl_surface,
 };
 use wayland_client::{Attached, DispatchData};
+use wayland_protocols::viewporter::client::{wp_viewport::WpViewport, wp_viewporter::WpViewporter};
 
 use log::error;
 
 use super::{ButtonState, Frame, FrameRequest, State, WindowState};
-use crate::seat::pointer::{ThemeManager, ThemeSpec, ThemedPointer};
-use crate::shm::AutoMemPool;
+use crate::seat::pointer::{CursorIcon, ThemeManager, ThemeSpec, ThemedPointer};
+use crate::shm::{AutoMemPool, SolidColorBuffers};
 
 /*
  * Drawing theme definitions
@@ -62,6 +63,7 @@ enum UIButton {
 struct Part {
     surface: wl_surface::WlSurface,
     subsurface: wl_subsurface::WlSubsurface,
+    viewport: Option<WpViewport>,
 }
 
 impl Part {
@@ -94,12 +96,38 @@ impl Part {
 
         let subsurface = subcompositor.get_subsurface(&surface, parent);
 
-        Part { surface, subsurface: subsurface.detach() }
+        Part { surface, subsurface: subsurface.detach(), viewport: None }
+    }
+
+    // A part filled with a single color, using a scaled 1x1 buffer
+    fn new_solid(
+        parent: &wl_surface::WlSurface,
+        compositor: &Attached<wl_compositor::WlCompositor>,
+        subcompositor: &Attached<wl_subcompositor::WlSubcompositor>,
+        viewporter: &Attached<WpViewporter>,
+    ) -> Part {
+        // the scale does not matter for a single color, the buffer scale stays 1, but the
+        // surface is still tracked by SCTK so that its scale factor can be queried
+        let surface = crate::surface::setup_surface(
+            compositor.create_surface(),
+            None::<fn(i32, wl_surface::WlSurface, DispatchData)>,
+        );
+        let viewport = viewporter.get_viewport(&surface);
+        let subsurface = subcompositor.get_subsurface(&surface, parent);
+
+        Part {
+            surface: surface.detach(),
+            subsurface: subsurface.detach(),
+            viewport: Some(viewport.detach()),
+        }
     }
 }
 
 impl Drop for Part {
     fn drop(&mut self) {
+        if let Some(ref viewport) = self.viewport {
+            viewport.destroy();
+        }
         self.subsurface.destroy();
         self.surface.destroy();
     }
@@ -235,6 +263,7 @@ pub struct FallbackFrame {
     subcompositor: Attached<wl_subcompositor::WlSubcompositor>,
     inner: Rc<RefCell<Inner>>,
     pool: AutoMemPool,
+    solid: Option<(Attached<WpViewporter>, SolidColorBuffers)>,
     active: WindowState,
     hidden: bool,
     pointers: Vec<ThemedPointer>,
@@ -277,6 +306,7 @@ impl Frame for FallbackFrame {
             subcompositor: subcompositor.clone(),
             inner,
             pool,
+            solid: None,
             active: WindowState::Inactive,
             hidden: true,
             pointers: Vec::new(),
@@ -405,6 +435,17 @@ impl Frame for FallbackFrame {
         let mut inner = self.inner.borrow_mut();
         if !self.hidden {
             if inner.parts.is_empty() {
+                let border = || match self.solid {
+                    Some((ref viewporter, _)) => Part::new_solid(
+                        &self.base_surface,
+                        &self.compositor,
+                        &self.subcompositor,
+                        viewporter,
+                    ),
+                    None => {
+                        Part::new(&self.base_surface, &self.compositor, &self.subcompositor, None)
+                    }
+                };
                 inner.parts = vec![
                     Part::new(
                         &self.base_surface,
@@ -412,10 +453,10 @@ impl Frame for FallbackFrame {
                         &self.subcompositor,
                         Some(Rc::clone(&self.inner)),
                     ),
-                    Part::new(&self.base_surface, &self.compositor, &self.subcompositor, None),
-                    Part::new(&self.base_surface, &self.compositor, &self.subcompositor, None),
-                    Part::new(&self.base_surface, &self.compositor, &self.subcompositor, None),
-                    Part::new(&self.base_surface, &self.compositor, &self.subcompositor, None),
+                    border(),
+                    border(),
+                    border(),
+                    border(),
                 ];
             }
         } else {
@@ -463,11 +504,12 @@ impl Frame for FallbackFrame {
 
         {
             // Create the buffers and draw
-            let color = if self.active == WindowState::Active {
-                PRIMARY_COLOR_ACTIVE.to_ne_bytes()
+            let argb = if self.active == WindowState::Active {
+                PRIMARY_COLOR_ACTIVE
             } else {
-                PRIMARY_COLOR_INACTIVE.to_ne_bytes()
+                PRIMARY_COLOR_INACTIVE
             };
+            let color = argb.to_ne_bytes();
 
             // -> head-subsurface
             if let Ok((canvas, buffer)) = self.pool.buffer(
@@ -522,7 +564,13 @@ impl Frame for FallbackFrame {
             }
 
             // -> top-subsurface
-            if let Ok((canvas, buffer)) = self.pool.buffer(
+            parts[TOP]
+                .subsurface
+                .set_position(-(BORDER_SIZE as i32), -(HEADER_SIZE as i32 + BORDER_SIZE as i32));
+            if fill_solid(&mut self.solid, &parts[TOP], width + 2 * BORDER_SIZE, BORDER_SIZE, argb)
+            {
+                // drawn with a scaled 1x1 buffer
+            } else if let Ok((canvas, buffer)) = self.pool.buffer(
                 ((width + 2 * BORDER_SIZE) * scales[TOP]) as i32,
                 (BORDER_SIZE * scales[TOP]) as i32,
                 (4 * scales[TOP] * (width + 2 * BORDER_SIZE)) as i32,
@@ -534,10 +582,6 @@ impl Frame for FallbackFrame {
                     pixel[2] = color[2];
                     pixel[3] = color[3];
                 }
-                parts[TOP].subsurface.set_position(
-                    -(BORDER_SIZE as i32),
-                    -(HEADER_SIZE as i32 + BORDER_SIZE as i32),
-                );
                 parts[TOP].surface.attach(Some(&buffer), 0, 0);
                 if self.surface_version >= 4 {
                     parts[TOP].surface.damage_buffer(
@@ -560,7 +604,16 @@ impl Frame for FallbackFrame {
             }
 
             // -> bottom-subsurface
-            if let Ok((canvas, buffer)) = self.pool.buffer(
+            parts[BOTTOM].subsurface.set_position(-(BORDER_SIZE as i32), height as i32);
+            if fill_solid(
+                &mut self.solid,
+                &parts[BOTTOM],
+                width + 2 * BORDER_SIZE,
+                BORDER_SIZE,
+                argb,
+            ) {
+                // drawn with a scaled 1x1 buffer
+            } else if let Ok((canvas, buffer)) = self.pool.buffer(
                 ((width + 2 * BORDER_SIZE) * scales[BOTTOM]) as i32,
                 (BORDER_SIZE * scales[BOTTOM]) as i32,
                 (4 * scales[BOTTOM] * (width + 2 * BORDER_SIZE)) as i32,
@@ -572,7 +625,6 @@ impl Frame for FallbackFrame {
                     pixel[2] = color[2];
                     pixel[3] = color[3];
                 }
-                parts[BOTTOM].subsurface.set_position(-(BORDER_SIZE as i32), height as i32);
                 parts[BOTTOM].surface.attach(Some(&buffer), 0, 0);
                 if self.surface_version >= 4 {
                     parts[BOTTOM].surface.damage_buffer(
@@ -595,7 +647,10 @@ impl Frame for FallbackFrame {
             }
 
             // -> left-subsurface
-            if let Ok((canvas, buffer)) = self.pool.buffer(
+            parts[LEFT].subsurface.set_position(-(BORDER_SIZE as i32), -(HEADER_SIZE as i32));
+            if fill_solid(&mut self.solid, &parts[LEFT], BORDER_SIZE, height + HEADER_SIZE, argb) {
+                // drawn with a scaled 1x1 buffer
+            } else if let Ok((canvas, buffer)) = self.pool.buffer(
                 (BORDER_SIZE * scales[LEFT]) as i32,
                 ((height + HEADER_SIZE) * scales[LEFT]) as i32,
                 4 * (BORDER_SIZE * scales[LEFT]) as i32,
@@ -607,7 +662,6 @@ impl Frame for FallbackFrame {
                     pixel[2] = color[2];
                     pixel[3] = color[3];
                 }
-                parts[LEFT].subsurface.set_position(-(BORDER_SIZE as i32), -(HEADER_SIZE as i32));
                 parts[LEFT].surface.attach(Some(&buffer), 0, 0);
                 if self.surface_version >= 4 {
                     parts[LEFT].surface.damage_buffer(
@@ -630,7 +684,10 @@ impl Frame for FallbackFrame {
             }
 
             // -> right-subsurface
-            if let Ok((canvas, buffer)) = self.pool.buffer(
+            parts[RIGHT].subsurface.set_position(width as i32, -(HEADER_SIZE as i32));
+            if fill_solid(&mut self.solid, &parts[RIGHT], BORDER_SIZE, height + HEADER_SIZE, argb) {
+                // drawn with a scaled 1x1 buffer
+            } else if let Ok((canvas, buffer)) = self.pool.buffer(
                 (BORDER_SIZE * scales[RIGHT]) as i32,
                 ((height + HEADER_SIZE) * scales[RIGHT]) as i32,
                 4 * (BORDER_SIZE * scales[RIGHT]) as i32,
@@ -642,7 +699,6 @@ impl Frame for FallbackFrame {
                     pixel[2] = color[2];
                     pixel[3] = color[3];
                 }
-                parts[RIGHT].subsurface.set_position(width as i32, -(HEADER_SIZE as i32));
                 parts[RIGHT].surface.attach(Some(&buffer), 0, 0);
                 if self.surface_version >= 4 {
                     parts[RIGHT].surface.damage_buffer(
@@ -693,6 +749,39 @@ impl Frame for FallbackFrame {
     fn set_config(&mut self, _config: ()) {}
 
     fn set_title(&mut self, _title: String) {}
+
+    fn set_solid_color_buffers(
+        &mut self,
+        viewporter: Attached<WpViewporter>,
+        buffers: SolidColorBuffers,
+    ) {
+        // parts created from now on use them
+        self.solid = Some((viewporter, buffers));
+    }
+}
+
+// Fill a part with a color if it is set up for it, returns whether it was drawn
+fn fill_solid(
+    solid: &mut Option<(Attached<WpViewporter>, SolidColorBuffers)>,
+    part: &Part,
+    width: u32,
+    height: u32,
+    color: u32,
+) -> bool {
+    let (viewport, buffers) = match (part.viewport.as_ref(), solid.as_mut()) {
+        (Some(viewport), Some((_, buffers))) => (viewport, buffers),
+        _ => return false,
+    };
+    match buffers.fill_surface(&part.surface, viewport, width as i32, height as i32, color) {
+        Ok(()) => {
+            part.surface.commit();
+            true
+        }
+        Err(e) => {
+            error!("Failed to draw the frame: {}", e);
+            false
+        }
+    }
 }
 
 impl Drop for FallbackFrame {
@@ -711,21 +800,21 @@ fn change_pointer(pointer: &ThemedPointer, inner: &Inner, location: Location, se
         return;
     }
 
-    let name = match location {
+    let icon = match location {
         // If we can't resize a frame we shouldn't show resize cursors.
-        _ if !inner.resizable => "left_ptr",
-        Location::Top => "top_side",
-        Location::TopRight => "top_right_corner",
-        Location::Right => "right_side",
-        Location::BottomRight => "bottom_right_corner",
-        Location::Bottom => "bottom_side",
-        Location::BottomLeft => "bottom_left_corner",
-        Location::Left => "left_side",
-        Location::TopLeft => "top_left_corner",
-        _ => "left_ptr",
+        _ if !inner.resizable => CursorIcon::Default,
+        Location::Top => CursorIcon::NResize,
+        Location::TopRight => CursorIcon::NeResize,
+        Location::Right => CursorIcon::EResize,
+        Location::BottomRight => CursorIcon::SeResize,
+        Location::Bottom => CursorIcon::SResize,
+        Location::BottomLeft => CursorIcon::SwResize,
+        Location::Left => CursorIcon::WResize,
+        Location::TopLeft => CursorIcon::NwResize,
+        _ => CursorIcon::Default,
     };
 
-    if pointer.set_cursor(name, serial).is_err() {
+    if pointer.set_cursor_icon(icon, serial).is_err() {
         error!("Failed to set cursor");
     }
 }
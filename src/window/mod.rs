@@ -17,10 +17,16 @@ use wayland_protocols::unstable::xdg_decoration::v1::client::{
     zxdg_toplevel_decoration_v1::{self, ZxdgToplevelDecorationV1},
 };
 
+use wayland_protocols::unstable::idle_inhibit::v1::client::zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1;
+use wayland_protocols::viewporter::client::wp_viewporter::WpViewporter;
+
 use crate::{
     environment::{Environment, GlobalHandler, MultiGlobalHandler},
+    idle_inhibit::IdleInhibitor,
+    protocols::staging::single_pixel_buffer::v1::client::wp_single_pixel_buffer_manager_v1::WpSinglePixelBufferManagerV1,
     seat::pointer::ThemeManager,
     shell,
+    shm::SolidColorBuffers,
 };
 
 mod fallback_frame;
@@ -174,6 +180,7 @@ pub struct Window<F: Frame> {
     decoration: Option<ZxdgToplevelDecorationV1>,
     shell_surface: Arc<Box<dyn shell::ShellSurface>>,
     inner: Rc<RefCell<Option<WindowInner<F>>>>,
+    idle_inhibitor: Option<IdleInhibitor>,
     _seat_listener: crate::seat::SeatListener,
 }
 
@@ -201,6 +208,8 @@ impl<F: Frame + 'static> Window<F> {
             + crate::shell::ShellHandling
             + MultiGlobalHandler<wl_seat::WlSeat>
             + GlobalHandler<ZxdgDecorationManagerV1>
+            + GlobalHandler<WpViewporter>
+            + GlobalHandler<WpSinglePixelBufferManagerV1>
             + crate::seat::SeatHandling,
     {
         let compositor = env.require_global::<wl_compositor::WlCompositor>();
@@ -239,6 +248,12 @@ impl<F: Frame + 'static> Window<F> {
             }) as Box<_>,
         )?;
 
+        // Let the frame draw its solid parts with scaled 1x1 buffers, if the compositor
+        // supports it.
+        if let Some(viewporter) = env.get_global::<WpViewporter>() {
+            frame.set_solid_color_buffers(viewporter, env.create_solid_color_buffers());
+        }
+
         let decoration_mgr = env.get_global::<ZxdgDecorationManagerV1>();
         if decoration_mgr.is_none() {
             // We don't have ServerSide decorations, so we'll be using CSD, and so should
@@ -386,6 +401,7 @@ impl<F: Frame + 'static> Window<F> {
             decoration,
             surface,
             inner,
+            idle_inhibitor: None,
             _seat_listener: seat_listener,
         };
 
@@ -436,6 +452,27 @@ impl<F: Frame + 'static> Window<F> {
         &self.surface
     }
 
+    /// Prevent the system from going idle while this window is visible
+    ///
+    /// The inhibition is owned by the window: it lasts until
+    /// [`uninhibit_idle`](#method.uninhibit_idle) is called or the window is dropped. Calling
+    /// this method again while the idle behavior is already inhibited does nothing.
+    pub fn inhibit_idle(&mut self, manager: &Attached<ZwpIdleInhibitManagerV1>) {
+        if self.idle_inhibitor.is_none() {
+            self.idle_inhibitor = Some(IdleInhibitor::new(manager, &self.surface));
+        }
+    }
+
+    /// Stop preventing the system from going idle while this window is visible
+    pub fn uninhibit_idle(&mut self) {
+        self.idle_inhibitor = None;
+    }
+
+    /// Whether this window prevents the system from going idle
+    pub fn is_idle_inhibited(&self) -> bool {
+        self.idle_inhibitor.is_some()
+    }
+
     /// Refreshes the frame
     ///
     /// Redraws the frame to match its requested state (dimensions, presence/
@@ -722,6 +759,7 @@ where
             .field("decoration", &self.decoration)
             .field("shell_surface", &self.shell_surface)
             .field("inner", &self.inner)
+            .field("idle_inhibitor", &self.idle_inhibitor)
             .field("_seat_listener", &self._seat_listener)
             .finish()
     }
@@ -817,6 +855,21 @@ pub trait Frame: Sized {
 
     /// Sets the frames title
     fn set_title(&mut self, title: String);
+
+    /// Provide the means to draw solid color parts of the frame cheaply
+    ///
+    /// This is called right after `init` if the compositor supports `wp_viewporter`. Surfaces
+    /// filled with a single color can then use a viewport and a 1x1 buffer from the
+    /// [`SolidColorBuffers`](../shm/struct.SolidColorBuffers.html) instead of a full-size
+    /// buffer. The default implementation ignores them.
+    ///
+    /// The `wp_viewporter` is the global of the environment: the frame must not destroy it.
+    fn set_solid_color_buffers(
+        &mut self,
+        _viewporter: Attached<WpViewporter>,
+        _buffers: SolidColorBuffers,
+    ) {
+    }
 }
 
 impl<E> Environment<E>
@@ -827,6 +880,8 @@ where
         + crate::shell::ShellHandling
         + MultiGlobalHandler<wl_seat::WlSeat>
         + GlobalHandler<ZxdgDecorationManagerV1>
+        + GlobalHandler<WpViewporter>
+        + GlobalHandler<WpSinglePixelBufferManagerV1>
         + crate::seat::SeatHandling,
 {
     /// Create a new window wrapping given surface